@@ -0,0 +1,240 @@
+//! Client library for the hi daemon: connect to the daemon's unix socket,
+//! register services, get and set daemon options, send messages and
+//! receive messages and events.
+
+use crate::message::{GetSet, Message};
+use crate::unix_socket::{UnixClient, UnixReceiver, UnixSender};
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use tokio::io;
+
+/// maximum number of messages kept while waiting for a reply, older
+/// messages are dropped
+const MAX_RECEIVED: usize = 1024;
+
+/// get default config directory of the daemon
+pub fn default_dir() -> Option<PathBuf> {
+    crate::config::default_dir()
+}
+
+/// create error for unexpected messages from the daemon
+fn unexpected_message(msg: &Message) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected message from daemon: {:?}", msg),
+    )
+}
+
+/// Client of the hi daemon
+pub struct Client {
+    client: UnixClient,
+    client_id: u16,
+    request_id: u32,
+    message_id: u32,
+    /// messages received while waiting for a reply, at most `MAX_RECEIVED`
+    received: VecDeque<Message>,
+}
+
+impl Client {
+    /// connect to the daemon running with config directory `dir`
+    pub async fn connect(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let client = UnixClient::connect(dir.as_ref()).await?;
+        Ok(Client {
+            client,
+            client_id: 0,
+            request_id: 0,
+            message_id: 0,
+            received: VecDeque::new(),
+        })
+    }
+
+    /// get id of this client assigned by the daemon on registration
+    pub fn client_id(&self) -> u16 {
+        self.client_id
+    }
+
+    /// register this client with `services` on the daemon and return the
+    /// client id
+    pub async fn register(&mut self, services: HashSet<u16>) -> io::Result<u16> {
+        self.client
+            .send_message(Message::Register { services })
+            .await?;
+        match self.client.receive_message().await? {
+            Message::RegisterOk { client_id } => {
+                self.client_id = client_id;
+                Ok(client_id)
+            }
+            Message::Error { message } => {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, message))
+            }
+            msg => Err(unexpected_message(&msg)),
+        }
+    }
+
+    /// send raw message to the daemon, replies of the daemon are returned
+    /// by `receive()`
+    pub async fn send(&mut self, msg: Message) -> io::Result<()> {
+        self.client.send_message(msg).await
+    }
+
+    /// receive next message or event from the daemon, replies of requests
+    /// sent by the other methods are returned by these methods. Messages
+    /// received while waiting for a reply are kept, but only the last
+    /// 1024 of them
+    pub async fn receive(&mut self) -> io::Result<Message> {
+        if let Some(msg) = self.received.pop_front() {
+            return Ok(msg);
+        }
+        self.client.receive_message().await
+    }
+
+    /// split client into a receiver of messages and events from the daemon
+    /// and a sender of raw messages. The receiver reads messages in a
+    /// separate task, so `receive()` can be cancelled, e.g., in
+    /// `tokio::select!`. Messages kept while waiting for a reply are
+    /// received first. Dropping the sender closes the connection
+    pub fn into_split(self) -> (Receiver, Sender) {
+        let (receiver, sender) = self.client.into_split();
+        let receiver = Receiver {
+            received: self.received,
+            receiver,
+        };
+        (receiver, Sender { sender })
+    }
+
+    /// send `request` and wait for the reply with the same request id,
+    /// other messages are kept for `receive()`
+    async fn request(&mut self, request: Message) -> io::Result<GetSet> {
+        self.client.send_message(request).await?;
+        let request_id = self.request_id;
+        self.request_id = self.request_id.wrapping_add(1);
+        loop {
+            match self.client.receive_message().await? {
+                Message::Get {
+                    request_id: id,
+                    content,
+                    ..
+                }
+                | Message::Set {
+                    request_id: id,
+                    content,
+                    ..
+                } if id == request_id => return Ok(content),
+                msg => self.keep(msg),
+            }
+        }
+    }
+
+    /// send `msg` and wait for the "ok" or "error" reply of the daemon,
+    /// other messages are kept for `receive()`
+    async fn send_and_wait(&mut self, msg: Message) -> io::Result<()> {
+        self.client.send_message(msg).await?;
+        loop {
+            match self.client.receive_message().await? {
+                Message::Ok => return Ok(()),
+                Message::Error { message } => return Err(io::Error::other(message)),
+                msg => self.keep(msg),
+            }
+        }
+    }
+
+    /// keep message `msg` for `receive()`, drop the oldest message if
+    /// there are too many
+    fn keep(&mut self, msg: Message) {
+        if self.received.len() >= MAX_RECEIVED {
+            self.received.pop_front();
+            error!("too many messages while waiting for reply, dropping oldest");
+        }
+        self.received.push_back(msg);
+    }
+
+    /// get information with `content` from the daemon and return the reply
+    pub async fn get(&mut self, content: GetSet) -> io::Result<GetSet> {
+        let msg = Message::Get {
+            client_id: self.client_id,
+            request_id: self.request_id,
+            content,
+        };
+        self.request(msg).await
+    }
+
+    /// set option with `content` on the daemon and return the reply
+    pub async fn set(&mut self, content: GetSet) -> io::Result<GetSet> {
+        let msg = Message::Set {
+            client_id: self.client_id,
+            request_id: self.request_id,
+            content,
+        };
+        self.request(msg).await
+    }
+
+    /// send `content` of `service` to client `to_client` on peer `to_peer`
+    /// and return the message id used in delivery events
+    pub async fn send_message(
+        &mut self,
+        to_peer: &str,
+        to_client: u16,
+        service: u16,
+        content: Vec<u8>,
+    ) -> io::Result<u32> {
+        self.message_id = self.message_id.wrapping_add(1);
+        let msg = Message::Message {
+            to_peer: to_peer.to_string(),
+            from_peer: String::new(),
+            to_client,
+            from_client: self.client_id,
+            service,
+            content,
+            message_id: self.message_id,
+        };
+        self.send_and_wait(msg).await?;
+        Ok(self.message_id)
+    }
+
+    /// subscribe to messages published on `topic`
+    pub async fn subscribe(&mut self, topic: &str) -> io::Result<()> {
+        let topic = topic.to_string();
+        self.send_and_wait(Message::Subscribe { topic }).await
+    }
+
+    /// unsubscribe from messages published on `topic`
+    pub async fn unsubscribe(&mut self, topic: &str) -> io::Result<()> {
+        let topic = topic.to_string();
+        self.send_and_wait(Message::Unsubscribe { topic }).await
+    }
+
+    /// publish `content` on `topic`
+    pub async fn publish(&mut self, topic: &str, content: Vec<u8>) -> io::Result<()> {
+        let topic = topic.to_string();
+        let msg = Message::Publish { topic, content };
+        self.send_and_wait(msg).await
+    }
+}
+
+/// Receiving half of a client, see `Client::into_split()`
+pub struct Receiver {
+    received: VecDeque<Message>,
+    receiver: UnixReceiver,
+}
+
+impl Receiver {
+    /// receive next message or event from the daemon, cancel safe
+    pub async fn receive(&mut self) -> io::Result<Message> {
+        if let Some(msg) = self.received.pop_front() {
+            return Ok(msg);
+        }
+        self.receiver.receive_message().await
+    }
+}
+
+/// Sending half of a client, see `Client::into_split()`
+pub struct Sender {
+    sender: UnixSender,
+}
+
+impl Sender {
+    /// send raw message to the daemon
+    pub async fn send(&mut self, msg: Message) -> io::Result<()> {
+        self.sender.send_message(msg).await
+    }
+}
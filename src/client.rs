@@ -1,5 +1,8 @@
 pub mod chat;
 pub mod file;
 pub mod get;
+pub mod ping;
 pub mod service;
 pub mod set;
+pub mod trace;
+pub mod watch;
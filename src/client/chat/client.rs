@@ -1,6 +1,6 @@
+use crate::api;
 use crate::config;
 use crate::message::{Event, Message, Service};
-use crate::unix_socket;
 use chrono::Local;
 use futures::future::FutureExt;
 use minicbor::{Decode, Encode};
@@ -21,8 +21,7 @@ struct ChatMessage {
 /// chat client
 struct ChatClient {
     config: config::Config,
-    client: unix_socket::UnixClient,
-    client_id: u16,
+    client: api::Client,
     name: String,
     destination: String,
     peers: HashMap<String, HashSet<u16>>,
@@ -30,32 +29,16 @@ struct ChatClient {
 
 impl ChatClient {
     /// create new chat client
-    pub async fn new(config: config::Config, client: unix_socket::UnixClient) -> Self {
+    pub async fn new(config: config::Config, client: api::Client) -> Self {
         ChatClient {
             config,
             client,
-            client_id: 0,
             name: String::new(),
             destination: String::from("all"),
             peers: HashMap::new(),
         }
     }
 
-    /// register this client
-    async fn register_client(&mut self) -> Result<(), Box<dyn Error>> {
-        let msg = Message::Register {
-            services: vec![Service::Chat as u16].into_iter().collect(),
-        };
-        self.client.send_message(msg).await?;
-        match self.client.receive_message().await? {
-            Message::RegisterOk { client_id } => {
-                self.client_id = client_id;
-                Ok(())
-            }
-            _ => Err("unexpected message from daemon".into()),
-        }
-    }
-
     /// handle "message" message coming from daemon
     async fn handle_message_message(
         &mut self,
@@ -89,7 +72,7 @@ impl ChatClient {
         event: Event,
     ) -> Result<(), Box<dyn Error>> {
         // make sure event is for us
-        if to_client != self.client_id {
+        if to_client != self.client.client_id() {
             error! {"received event for other client"};
             return Ok(());
         }
@@ -102,6 +85,15 @@ impl ChatClient {
                     self.peers = peers;
                 }
             }
+            Event::MessageFailed(_, peer, e) => {
+                let now = Local::now();
+                println!(
+                    "{}: sending message to {} failed: {}",
+                    now.format("%H:%M:%S"),
+                    peer,
+                    e
+                );
+            }
             _ => (),
         }
         Ok(())
@@ -143,39 +135,22 @@ impl ChatClient {
         };
         minicbor::encode(message, &mut content)?;
 
-        // send message to everyone
+        // broadcast message to everyone
+        let service = Service::Chat as u16;
         if self.destination == "all" {
-            for (peer, clients) in self.peers.iter() {
-                for client in clients.iter() {
-                    let msg = Message::Message {
-                        to_peer: peer.clone(),
-                        from_peer: "".into(),
-                        to_client: *client,
-                        from_client: self.client_id,
-                        service: Service::Chat as u16,
-                        content: content.clone(),
-                    };
-                    self.client.send_message(msg).await?;
-                }
-            }
+            self.client
+                .send_message(Message::ALL_PEERS, Message::ALL_CLIENTS, service, content)
+                .await?;
             return Ok(());
         }
 
         // send message to specific peer
-        if self.peers.contains_key(&self.destination) {
-            let clients = self.peers.get(&self.destination).unwrap();
-            for client in clients.iter() {
-                let msg = Message::Message {
-                    to_peer: self.destination.clone(),
-                    from_peer: "".into(),
-                    to_client: *client,
-                    from_client: self.client_id,
-                    service: Service::Chat as u16,
-                    content: content.clone(),
-                };
-                self.client.send_message(msg).await?;
+        if let Some(clients) = self.peers.get(&self.destination) {
+            for client in clients.clone() {
+                self.client
+                    .send_message(&self.destination, client, service, content.clone())
+                    .await?;
             }
-            return Ok(());
         }
         Ok(())
     }
@@ -196,7 +171,8 @@ impl ChatClient {
         };
 
         // register this client and enable chat mode
-        self.register_client().await?;
+        let services = vec![Service::Chat as u16].into_iter().collect();
+        self.client.register(services).await?;
 
         // enter chat mode
         println!("Chat mode:");
@@ -204,7 +180,7 @@ impl ChatClient {
         loop {
             tokio::select! {
                 // handle message coming from daemon
-                msg = self.client.receive_message().fuse() => {
+                msg = self.client.receive().fuse() => {
                     match msg {
                         Ok(msg) => self.handle_message(msg).await?,
                         Err(e) => return Err(e.into()),
@@ -226,7 +202,7 @@ impl ChatClient {
 
 /// run daemon client in chat mode
 pub async fn run(config: config::Config) {
-    match unix_socket::UnixClient::connect(&config).await {
+    match api::Client::connect(config.dir.clone().unwrap()).await {
         Ok(client) => {
             if let Err(e) = ChatClient::new(config, client).await.run().await {
                 error!("{}", e);
@@ -1,9 +1,9 @@
+use crate::api;
 use crate::config;
 use crate::message::{Event, Message, Service};
-use crate::unix_socket;
 use futures::future::FutureExt;
 use minicbor::{Decode, Encode};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::error::Error;
 use std::path;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -37,6 +37,19 @@ enum FileMessage {
     ChunkAck(#[n(0)] u32),
 }
 
+impl FileMessage {
+    /// get message id for daemon messages: id of the file transfer or
+    /// 0 if the message does not belong to a transfer
+    fn message_id(&self) -> u32 {
+        match self {
+            FileMessage::Get(id, ..) | FileMessage::Chunk(id, ..) | FileMessage::ChunkAck(id) => {
+                *id
+            }
+            FileMessage::List | FileMessage::ListReply(..) => 0,
+        }
+    }
+}
+
 /// file transfer state
 #[derive(Debug)]
 enum FTState {
@@ -370,21 +383,19 @@ impl FileTransfer {
 /// file client
 struct FileClient {
     _config: config::Config,
-    client: unix_socket::UnixClient,
+    client: api::Client,
     client_id: u16,
-    peers: HashMap<String, HashSet<u16>>,
     shares: Vec<(String, u64)>,
     transfers: HashMap<u32, FileTransfer>,
 }
 
 impl FileClient {
     /// create new file Client
-    pub async fn new(_config: config::Config, client: unix_socket::UnixClient) -> Self {
+    pub async fn new(_config: config::Config, client: api::Client) -> Self {
         FileClient {
             _config,
             client,
             client_id: 0,
-            peers: HashMap::new(),
             shares: Vec::new(),
             transfers: HashMap::new(),
         }
@@ -392,17 +403,9 @@ impl FileClient {
 
     /// register this client
     async fn register_client(&mut self) -> Result<(), Box<dyn Error>> {
-        let msg = Message::Register {
-            services: vec![Service::File as u16].into_iter().collect(),
-        };
-        self.client.send_message(msg).await?;
-        match self.client.receive_message().await? {
-            Message::RegisterOk { client_id } => {
-                self.client_id = client_id;
-                Ok(())
-            }
-            _ => Err("unexpected message from daemon".into()),
-        }
+        let services = vec![Service::File as u16].into_iter().collect();
+        self.client_id = self.client.register(services).await?;
+        Ok(())
     }
 
     /// run file client
@@ -419,7 +422,7 @@ impl FileClient {
         loop {
             tokio::select! {
                 // handle message coming from daemon
-                msg = self.client.receive_message().fuse() => {
+                msg = self.client.receive().fuse() => {
                     if let Ok(msg) = msg {
                         self.handle_daemon_message(msg).await?;
                     }
@@ -496,6 +499,7 @@ impl FileClient {
 
         // if there is a response file message, create daemon message and return it
         if let Some(response) = response {
+            let message_id = response.message_id();
             let mut content = Vec::new();
             if let Err(e) = minicbor::encode(response, &mut content) {
                 error!("error encoding file message: {}", e);
@@ -508,6 +512,7 @@ impl FileClient {
                 from_client: self.client_id,
                 service: Service::File as u16,
                 content,
+                message_id,
             });
         }
         None
@@ -548,10 +553,14 @@ impl FileClient {
 
         // handle events
         match event {
-            Event::ServiceUpdate(service, peers) => {
-                // check if service is correct and update peers
-                if service == Service::File as u16 {
-                    self.peers = peers;
+            Event::MessageFailed(id, peer, e) => {
+                // stop file transfer if its message could not be delivered
+                match self.transfers.get_mut(&id) {
+                    Some(transfer) => {
+                        error!("message for transfer {} to {} failed: {}", id, peer, e);
+                        transfer.complete(Some(format!("Message failed: {}", e)));
+                    }
+                    None => error!("message to {} failed: {}", peer, e),
                 }
             }
             _ => (),
@@ -591,7 +600,7 @@ impl FileClient {
         };
 
         if let Some(reply) = reply {
-            self.client.send_message(reply).await?;
+            self.client.send(reply).await?;
         }
         Ok(())
     }
@@ -600,19 +609,10 @@ impl FileClient {
     async fn handle_user_command_ls(&mut self) -> Result<(), Box<dyn Error>> {
         let mut content = Vec::new();
         minicbor::encode(FileMessage::List, &mut content)?;
-        for (peer, clients) in self.peers.iter() {
-            for client in clients.iter() {
-                let message = Message::Message {
-                    to_peer: peer.clone(),
-                    from_peer: String::new(),
-                    to_client: *client,
-                    from_client: self.client_id,
-                    service: Service::File as u16,
-                    content: content.clone(),
-                };
-                self.client.send_message(message).await?;
-            }
-        }
+        let service = Service::File as u16;
+        self.client
+            .send_message(Message::ALL_PEERS, Message::ALL_CLIENTS, service, content)
+            .await?;
         Ok(())
     }
 
@@ -651,6 +651,7 @@ impl FileClient {
 
         // create and send message
         if let Some(next) = self.transfers.get_mut(&id).unwrap().next().await {
+            let message_id = next.message_id();
             let mut content = Vec::new();
             minicbor::encode(next, &mut content)?;
             let message = Message::Message {
@@ -660,8 +661,9 @@ impl FileClient {
                 from_client: self.client_id,
                 service: Service::File as u16,
                 content,
+                message_id,
             };
-            self.client.send_message(message).await?;
+            self.client.send(message).await?;
         };
         Ok(())
     }
@@ -789,7 +791,7 @@ impl FileClient {
 
 /// run daemon client in file mode
 pub async fn run(config: config::Config) {
-    match unix_socket::UnixClient::connect(&config).await {
+    match api::Client::connect(config.dir.clone().unwrap()).await {
         Ok(client) => {
             if let Err(e) = FileClient::new(config, client).await.run().await {
                 error!("{}", e);
@@ -1,6 +1,6 @@
+use crate::api;
 use crate::config;
-use crate::message::{GetSet, Message};
-use crate::unix_socket;
+use crate::message::GetSet;
 use std::collections::HashSet;
 use std::error::Error;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -8,47 +8,13 @@ use std::time::{SystemTime, UNIX_EPOCH};
 /// get client
 struct GetClient {
     config: config::Config,
-    client: unix_socket::UnixClient,
-    client_id: u16,
-    request_id: u32,
+    client: api::Client,
 }
 
 impl GetClient {
     /// create new get client
-    async fn new(config: config::Config, client: unix_socket::UnixClient) -> Self {
-        GetClient {
-            config,
-            client,
-            client_id: 0,
-            request_id: 0,
-        }
-    }
-
-    /// register this client
-    async fn register_client(&mut self) -> Result<(), Box<dyn Error>> {
-        let msg = Message::Register {
-            services: HashSet::new(),
-        };
-        self.client.send_message(msg).await?;
-        match self.client.receive_message().await? {
-            Message::RegisterOk { client_id } => {
-                self.client_id = client_id;
-                Ok(())
-            }
-            _ => Err("unexpected message from daemon".into()),
-        }
-    }
-
-    /// send get request
-    async fn send_request(&mut self, content: GetSet) -> Result<(), Box<dyn Error>> {
-        let msg = Message::Get {
-            client_id: self.client_id,
-            request_id: self.request_id,
-            content,
-        };
-        self.client.send_message(msg).await?;
-        self.request_id = self.request_id.wrapping_add(1);
-        Ok(())
+    async fn new(config: config::Config, client: api::Client) -> Self {
+        GetClient { config, client }
     }
 
     /// handle content of a get reply
@@ -59,6 +25,68 @@ impl GetClient {
             .as_secs();
         match content {
             GetSet::Name(name) => println!("Name: {}", name),
+            GetSet::PeerId(peer_id) => println!("Peer ID: {}", peer_id),
+            GetSet::Addresses(addresses) => {
+                println!("Addresses:");
+                for address in addresses {
+                    println!("  {}", address);
+                }
+            }
+            GetSet::Blocked(peers) => {
+                println!("Blocked peers:");
+                for peer in peers {
+                    println!("  {}", peer);
+                }
+            }
+            GetSet::Allowed(peers) => {
+                println!("Allowed peers:");
+                for peer in peers {
+                    println!("  {}", peer);
+                }
+            }
+            GetSet::Outbox(messages) => {
+                println!("Outbox:");
+                for message in messages {
+                    println!(
+                        "  \
+                        id: {}, \
+                        to_peer: {}, \
+                        to_client: {}, \
+                        from_client: {}, \
+                        service: {}, \
+                        size: {}, \
+                        age: {}s, \
+                        expires: {}s",
+                        message.id,
+                        message.to_peer,
+                        message.to_client,
+                        message.from_client,
+                        message.service,
+                        message.size,
+                        current_secs.saturating_sub(message.created),
+                        message.expires.saturating_sub(current_secs),
+                    );
+                }
+            }
+            GetSet::Clients(clients) => {
+                println!("Clients:");
+                for client in clients {
+                    println!(
+                        "  \
+                        client_id: {}, \
+                        uid: {}, \
+                        pid: {}, \
+                        services: {:?}",
+                        client.client_id,
+                        client.uid,
+                        match client.pid {
+                            Some(pid) => pid.to_string(),
+                            None => String::from("-"),
+                        },
+                        client.services,
+                    );
+                }
+            }
             GetSet::Peers(peers) => {
                 println!("Peers:");
                 for peer in peers {
@@ -67,12 +95,41 @@ impl GetClient {
                         peer_id: {}, \
                         name: {:?}, \
                         services_tag: {}, \
-                        last_update: {}s",
+                        last_update: {}s, \
+                        connected: {}, \
+                        rtt: {}",
                         peer.peer_id,
                         peer.name,
                         peer.services_tag,
                         current_secs - peer.last_update,
+                        peer.connected,
+                        match peer.rtt {
+                            Some(rtt) => format!("{:.3}ms", rtt as f64 / 1000.0),
+                            None => String::from("-"),
+                        },
+                    );
+                    println!(
+                        "    \
+                        agent_version: {:?}, \
+                        protocol_version: {:?}, \
+                        observed_addr: {:?}",
+                        peer.agent_version, peer.protocol_version, peer.observed_addr,
+                    );
+                    println!(
+                        "    \
+                        version: {}, \
+                        request_versions: {:?}{}",
+                        peer.version,
+                        peer.request_versions,
+                        if peer.version_mismatch {
+                            ", version mismatch"
+                        } else {
+                            ""
+                        },
                     );
+                    for addr in peer.listen_addrs {
+                        println!("    listen_addr: {}", addr);
+                    }
                 }
             }
             GetSet::Error(e) => eprintln!("Error: {}", e),
@@ -80,19 +137,10 @@ impl GetClient {
         }
     }
 
-    /// handle get reply
-    async fn handle_reply(&mut self) -> Result<(), Box<dyn Error>> {
-        match self.client.receive_message().await? {
-            Message::Get { content, .. } => self.handle_reply_content(content).await,
-            msg => println!("{:?}", msg),
-        }
-        Ok(())
-    }
-
     /// run get client
     async fn run(&mut self) -> Result<(), Box<dyn Error>> {
         // register client
-        self.register_client().await?;
+        self.client.register(HashSet::new()).await?;
 
         // get options to get from config
         let options = match self.config.command {
@@ -105,13 +153,19 @@ impl GetClient {
             let content = match option.as_str() {
                 "name" => GetSet::Name(String::new()),
                 "peers" => GetSet::Peers(Vec::new()),
+                "peer-id" => GetSet::PeerId(String::new()),
+                "addresses" => GetSet::Addresses(Vec::new()),
+                "blocked" => GetSet::Blocked(Vec::new()),
+                "allowed" => GetSet::Allowed(Vec::new()),
+                "outbox" => GetSet::Outbox(Vec::new()),
+                "clients" => GetSet::Clients(Vec::new()),
                 _ => {
                     error!("error getting unknown configuration option: {}", option);
                     continue;
                 }
             };
-            self.send_request(content).await?;
-            self.handle_reply().await?;
+            let reply = self.client.get(content).await?;
+            self.handle_reply_content(reply).await;
         }
         Ok(())
     }
@@ -119,7 +173,7 @@ impl GetClient {
 
 /// run get client
 pub async fn run(config: config::Config) {
-    match unix_socket::UnixClient::connect(&config).await {
+    match api::Client::connect(config.dir.clone().unwrap()).await {
         Ok(client) => {
             if let Err(e) = GetClient::new(config, client).await.run().await {
                 error!("{}", e);
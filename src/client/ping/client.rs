@@ -0,0 +1,92 @@
+use crate::api;
+use crate::config;
+use crate::message::GetSet;
+use std::collections::HashSet;
+use std::error::Error;
+use tokio::time::{self, Duration};
+
+/// ping client
+struct PingClient {
+    config: config::Config,
+    client: api::Client,
+}
+
+impl PingClient {
+    /// create new ping client
+    async fn new(config: config::Config, client: api::Client) -> Self {
+        PingClient { config, client }
+    }
+
+    /// send ping request to `peer` and wait for the round-trip time in microseconds
+    async fn ping(&mut self, peer: &str) -> Result<Result<u64, String>, Box<dyn Error>> {
+        let content = GetSet::Ping(peer.to_string(), 0);
+        match self.client.get(content).await? {
+            GetSet::Ping(_, rtt) => Ok(Ok(rtt)),
+            GetSet::Error(e) => Ok(Err(e)),
+            content => Ok(Err(format!("unexpected reply: {:?}", content))),
+        }
+    }
+
+    /// run ping client
+    async fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        // register client
+        self.client.register(HashSet::new()).await?;
+
+        // get peer and number of pings from config
+        let (peer, count) = match self.config.command {
+            Some(config::Command::Ping(ref ping_opts)) => {
+                (ping_opts.peer.clone(), ping_opts.count)
+            }
+            _ => return Err("invalid config".into()),
+        };
+
+        // send pings and print round-trip times
+        println!("PING {}", peer);
+        let mut rtts = Vec::new();
+        let mut sent = 0;
+        let mut timer = time::interval(Duration::from_secs(1));
+        while count.is_none_or(|count| sent < count) {
+            timer.tick().await;
+            match self.ping(&peer).await? {
+                Ok(rtt) => {
+                    println!("seq={} time={:.3} ms", sent, rtt as f64 / 1000.0);
+                    rtts.push(rtt);
+                }
+                Err(e) => println!("seq={} error: {}", sent, e),
+            }
+            sent += 1;
+        }
+
+        // print statistics
+        let received = rtts.len() as u32;
+        let loss = match sent {
+            0 => 0,
+            sent => 100 * (sent - received) / sent,
+        };
+        println!("--- {} ping statistics ---", peer);
+        println!("{} sent, {} received, {}% loss", sent, received, loss);
+        if let (Some(min), Some(max)) = (rtts.iter().min(), rtts.iter().max()) {
+            let avg = rtts.iter().sum::<u64>() / rtts.len() as u64;
+            println!(
+                "rtt min/avg/max = {:.3}/{:.3}/{:.3} ms",
+                *min as f64 / 1000.0,
+                avg as f64 / 1000.0,
+                *max as f64 / 1000.0
+            );
+        }
+        Ok(())
+    }
+}
+
+/// run ping client
+pub async fn run(config: config::Config) {
+    match api::Client::connect(config.dir.clone().unwrap()).await {
+        Ok(client) => {
+            if let Err(e) = PingClient::new(config, client).await.run().await {
+                error!("{}", e);
+            }
+        }
+        Err(e) => error!("unix socket client error: {}", e),
+    }
+    debug!("ping client stopped");
+}
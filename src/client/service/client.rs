@@ -1,6 +1,6 @@
+use crate::api;
 use crate::config;
 use crate::message::{Event, GetSet, Message, PeerInfo, Service};
-use crate::unix_socket;
 use minicbor::{Decode, Encode};
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
@@ -49,16 +49,20 @@ impl ServiceMap {
 /// service client
 struct ServiceClient {
     _config: config::Config,
-    client: unix_socket::UnixClient,
+    client: api::Client,
     client_id: ClientId,
     request_id: u32,
     local: ServiceMap,
     peers: HashMap<String, ServiceMap>,
+    /// local clients watching service updates of all services
+    watchers: HashSet<ClientId>,
+    /// services in the last service updates of the watching clients
+    watched: HashSet<ServiceId>,
 }
 
 impl ServiceClient {
     /// create new service client
-    pub async fn new(config: config::Config, client: unix_socket::UnixClient) -> Self {
+    pub async fn new(config: config::Config, client: api::Client) -> Self {
         ServiceClient {
             _config: config,
             client,
@@ -66,6 +70,8 @@ impl ServiceClient {
             request_id: 0,
             peers: HashMap::new(),
             local: ServiceMap::new(),
+            watchers: HashSet::new(),
+            watched: HashSet::new(),
         }
     }
 
@@ -77,17 +83,9 @@ impl ServiceClient {
 
     /// register this client
     async fn register_client(&mut self) -> Result<(), Box<dyn Error>> {
-        let msg = Message::Register {
-            services: vec![Service::Service as ServiceId].into_iter().collect(),
-        };
-        self.client.send_message(msg).await?;
-        match self.client.receive_message().await? {
-            Message::RegisterOk { client_id } => {
-                self.client_id = client_id;
-                Ok(())
-            }
-            _ => Err("unexpected message from daemon".into()),
-        }
+        let services = vec![Service::Service as ServiceId].into_iter().collect();
+        self.client_id = self.client.register(services).await?;
+        Ok(())
     }
 
     /// send service message to other peer
@@ -106,8 +104,9 @@ impl ServiceClient {
             from_client: self.client_id,
             service: Service::Service as ServiceId,
             content,
+            message_id: 0,
         };
-        self.client.send_message(msg).await?;
+        self.client.send(msg).await?;
         Ok(())
     }
 
@@ -122,17 +121,33 @@ impl ServiceClient {
             request_id: self.get_request_id(),
             content: GetSet::ServicesTag(self.local.services_tag),
         };
-        self.client.send_message(msg).await?;
+        self.client.send(msg).await?;
         Ok(())
     }
 
     /// update services and send service updates to interested clients
     async fn update_services(&mut self) -> Result<(), Box<dyn Error>> {
-        // for every service a local client is interested in...
-        for (s, clients) in self.local.services.iter() {
+        // get every service a local client is interested in, watching
+        // clients are interested in all services including the ones that
+        // disappeared since the last update
+        let mut services: HashSet<ServiceId> = self.local.services.keys().cloned().collect();
+        if !self.watchers.is_empty() {
+            for peer in self.peers.values() {
+                services.extend(peer.services.keys());
+            }
+            services.extend(&self.watched);
+        }
+        let mut watched = HashSet::new();
+
+        // for every service...
+        for s in services {
             // (1) get local clients
             let mut map = HashMap::<String, HashSet<ClientId>>::new();
-            map.insert("".into(), clients.clone());
+            let mut clients = HashSet::new();
+            if let Some(local_clients) = self.local.services.get(&s) {
+                map.insert("".into(), local_clients.clone());
+                clients.extend(local_clients);
+            }
 
             // (2) find peers and their clients
             for (peer_id, peer) in self.peers.iter() {
@@ -141,16 +156,21 @@ impl ServiceClient {
                 }
             }
 
-            // (2) send to local clients
-            for client_id in clients.iter() {
+            // (3) send to local and watching clients
+            if !map.is_empty() {
+                watched.insert(s);
+            }
+            clients.extend(&self.watchers);
+            for client_id in clients {
                 let event = Message::Event {
-                    to_client: *client_id,
+                    to_client: client_id,
                     from_client: self.client_id,
-                    event: Event::ServiceUpdate(*s, map.clone()),
+                    event: Event::ServiceUpdate(s, map.clone()),
                 };
-                self.client.send_message(event).await?;
+                self.client.send(event).await?;
             }
         }
+        self.watched = watched;
         Ok(())
     }
 
@@ -159,8 +179,14 @@ impl ServiceClient {
         &mut self,
         mut add: bool,
         client_id: ClientId,
-        services: HashSet<ServiceId>,
+        mut services: HashSet<ServiceId>,
     ) -> Result<(), Box<dyn Error>> {
+        // update watching clients, the watch service is not announced
+        self.watchers.remove(&client_id);
+        if add && services.remove(&(Service::Watch as ServiceId)) {
+            self.watchers.insert(client_id);
+        }
+
         // treat empty services as remove
         if services.is_empty() {
             add = false;
@@ -227,6 +253,15 @@ impl ServiceClient {
         Ok(())
     }
 
+    /// handle PeerRemoved "event" message
+    async fn handle_event_peer_removed(&mut self, peer_id: String) -> Result<(), Box<dyn Error>> {
+        // remove peer entry and send service updates without the peer
+        if self.peers.remove(&peer_id).is_some() {
+            self.update_services().await?;
+        }
+        Ok(())
+    }
+
     /// handle "event" message
     async fn handle_event(&mut self, event: Event) -> Result<(), Box<dyn Error>> {
         match event {
@@ -235,6 +270,7 @@ impl ServiceClient {
                     .await?
             }
             Event::PeerUpdate(peer_info) => self.handle_event_peer_update(peer_info).await?,
+            Event::PeerRemoved(peer_id) => self.handle_event_peer_removed(peer_id).await?,
             _ => (),
         }
         Ok(())
@@ -304,7 +340,7 @@ impl ServiceClient {
     pub async fn run(&mut self) -> Result<(), Box<dyn Error>> {
         self.register_client().await?;
         loop {
-            let msg = self.client.receive_message().await?;
+            let msg = self.client.receive().await?;
             debug!("received message {:?}", msg);
             match msg {
                 Message::Event { event, .. } => self.handle_event(event).await?,
@@ -323,7 +359,7 @@ impl ServiceClient {
 /// run daemon client in service mode
 pub async fn run(config: config::Config) {
     tokio::spawn(async {
-        match unix_socket::UnixClient::connect(&config).await {
+        match api::Client::connect(config.dir.clone().unwrap()).await {
             Ok(client) => {
                 if let Err(e) = ServiceClient::new(config, client).await.run().await {
                     error!("{}", e);
@@ -1,53 +1,19 @@
+use crate::api;
 use crate::config;
-use crate::message::{GetSet, Message};
-use crate::unix_socket;
+use crate::message::GetSet;
 use std::collections::HashSet;
 use std::error::Error;
 
 /// set client
 struct SetClient {
     config: config::Config,
-    client: unix_socket::UnixClient,
-    client_id: u16,
-    request_id: u32,
+    client: api::Client,
 }
 
 impl SetClient {
     /// create new set client
-    async fn new(config: config::Config, client: unix_socket::UnixClient) -> Self {
-        SetClient {
-            config,
-            client,
-            client_id: 0,
-            request_id: 0,
-        }
-    }
-
-    /// register this client
-    async fn register_client(&mut self) -> Result<(), Box<dyn Error>> {
-        let msg = Message::Register {
-            services: HashSet::new(),
-        };
-        self.client.send_message(msg).await?;
-        match self.client.receive_message().await? {
-            Message::RegisterOk { client_id } => {
-                self.client_id = client_id;
-                Ok(())
-            }
-            _ => Err("unexpected message from daemon".into()),
-        }
-    }
-
-    /// send set request
-    async fn send_request(&mut self, content: GetSet) -> Result<(), Box<dyn Error>> {
-        let msg = Message::Set {
-            client_id: self.client_id,
-            request_id: self.request_id,
-            content,
-        };
-        self.client.send_message(msg).await?;
-        self.request_id = self.request_id.wrapping_add(1);
-        Ok(())
+    async fn new(config: config::Config, client: api::Client) -> Self {
+        SetClient { config, client }
     }
 
     /// handle content of a set reply
@@ -59,19 +25,10 @@ impl SetClient {
         }
     }
 
-    /// handle set reply
-    async fn handle_reply(&mut self) -> Result<(), Box<dyn Error>> {
-        match self.client.receive_message().await? {
-            Message::Set { content, .. } => self.handle_reply_content(content).await,
-            msg => println!("{:?}", msg),
-        }
-        Ok(())
-    }
-
     /// run set client
     async fn run(&mut self) -> Result<(), Box<dyn Error>> {
         // register client
-        self.register_client().await?;
+        self.client.register(HashSet::new()).await?;
 
         // get options to set from config
         let options = match self.config.command {
@@ -84,6 +41,16 @@ impl SetClient {
             let content = match option.name.as_str() {
                 "name" => GetSet::Name(option.value.to_string()),
                 "connect" => GetSet::Connect(option.value.to_string()),
+                "listen" => GetSet::Listen(option.value.to_string()),
+                "unlisten" => GetSet::Unlisten(option.value.to_string()),
+                "block" => GetSet::Block(option.value.to_string()),
+                "unblock" => GetSet::Unblock(option.value.to_string()),
+                "allow" => GetSet::Allow(option.value.to_string()),
+                "disallow" => GetSet::Disallow(option.value.to_string()),
+                "purge-outbox" => match option.value.as_str() {
+                    "all" => GetSet::PurgeOutbox(String::new()),
+                    peer => GetSet::PurgeOutbox(peer.to_string()),
+                },
                 _ => {
                     error!(
                         "error setting unknown configuration option: {}",
@@ -92,8 +59,8 @@ impl SetClient {
                     continue;
                 }
             };
-            self.send_request(content).await?;
-            self.handle_reply().await?;
+            let reply = self.client.set(content).await?;
+            self.handle_reply_content(reply).await;
         }
         Ok(())
     }
@@ -101,7 +68,7 @@ impl SetClient {
 
 /// run set client
 pub async fn run(config: config::Config) {
-    match unix_socket::UnixClient::connect(&config).await {
+    match api::Client::connect(config.dir.clone().unwrap()).await {
         Ok(client) => {
             if let Err(e) = SetClient::new(config, client).await.run().await {
                 error!("{}", e);
@@ -0,0 +1,66 @@
+use crate::api;
+use crate::config;
+use crate::message::GetSet;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+/// trace client
+struct TraceClient {
+    config: config::Config,
+    client: api::Client,
+}
+
+impl TraceClient {
+    /// create new trace client
+    async fn new(config: config::Config, client: api::Client) -> Self {
+        TraceClient { config, client }
+    }
+
+    /// run trace client
+    async fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        // register client
+        self.client.register(HashSet::new()).await?;
+
+        // get peer from config
+        let peer = match self.config.command {
+            Some(config::Command::Trace(ref trace_opts)) => trace_opts.peer.clone(),
+            _ => return Err("invalid config".into()),
+        };
+
+        // get names of known peers
+        let names: HashMap<String, String> =
+            match self.client.get(GetSet::Peers(Vec::new())).await? {
+                GetSet::Peers(peers) => peers.into_iter().map(|p| (p.peer_id, p.name)).collect(),
+                _ => HashMap::new(),
+            };
+
+        // trace peer and print path
+        println!("TRACE {}", peer);
+        match self.client.get(GetSet::Trace(peer, Vec::new())).await? {
+            GetSet::Trace(_, path) => {
+                for (hop, peer_id) in path.iter().enumerate() {
+                    match names.get(peer_id) {
+                        Some(name) => println!("{}: {} ({})", hop, peer_id, name),
+                        None => println!("{}: {}", hop, peer_id),
+                    }
+                }
+            }
+            GetSet::Error(e) => println!("error: {}", e),
+            content => println!("unexpected reply: {:?}", content),
+        }
+        Ok(())
+    }
+}
+
+/// run trace client
+pub async fn run(config: config::Config) {
+    match api::Client::connect(config.dir.clone().unwrap()).await {
+        Ok(client) => {
+            if let Err(e) = TraceClient::new(config, client).await.run().await {
+                error!("{}", e);
+            }
+        }
+        Err(e) => error!("unix socket client error: {}", e),
+    }
+    debug!("trace client stopped");
+}
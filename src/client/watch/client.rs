@@ -0,0 +1,109 @@
+use crate::api;
+use crate::config;
+use crate::message::{Event, Message, Service};
+use chrono::Local;
+use std::collections::HashSet;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// watch client
+struct WatchClient {
+    config: config::Config,
+    client: api::Client,
+    json: bool,
+}
+
+impl WatchClient {
+    /// create new watch client
+    async fn new(config: config::Config, client: api::Client) -> Self {
+        WatchClient {
+            config,
+            client,
+            json: false,
+        }
+    }
+
+    /// print `event` as JSON line
+    fn print_json(&self, event: &Event) {
+        let current_secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("timestamp error")
+            .as_secs();
+        let line = serde_json::json!({ "time": current_secs, "event": event });
+        println!("{}", line);
+    }
+
+    /// print `event` as text line
+    fn print_text(&self, event: Event) {
+        let text = match event {
+            Event::PeerUpdate(peer) => format!(
+                "peer {} announced: name: {:?}, services_tag: {}, connected: {}",
+                peer.peer_id, peer.name, peer.services_tag, peer.connected
+            ),
+            Event::PeerRemoved(peer_id) => format!("peer {} removed", peer_id),
+            Event::PeerConnection(peer_id, true) => {
+                format!("connection to peer {} opened", peer_id)
+            }
+            Event::PeerConnection(peer_id, false) => {
+                format!("connection to peer {} closed", peer_id)
+            }
+            Event::ClientUpdate(true, client_id, services) => {
+                format!("client {} added: services: {:?}", client_id, services)
+            }
+            Event::ClientUpdate(false, client_id, _) => format!("client {} removed", client_id),
+            Event::ServiceUpdate(service, peers) => {
+                let mut peers: Vec<String> = peers
+                    .into_iter()
+                    .map(|(peer_id, clients)| match peer_id.as_str() {
+                        "" => format!("local: {:?}", clients),
+                        _ => format!("{}: {:?}", peer_id, clients),
+                    })
+                    .collect();
+                peers.sort();
+                format!("service {} updated: {}", service, peers.join(", "))
+            }
+            _ => return,
+        };
+        let now = Local::now();
+        println!("{}: {}", now.format("%H:%M:%S"), text);
+    }
+
+    /// run watch client
+    async fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        // get output format from config
+        self.json = match self.config.command {
+            Some(config::Command::Watch(ref watch_opts)) => watch_opts.json,
+            _ => return Err("invalid config".into()),
+        };
+
+        // register client for daemon and service events
+        let services: HashSet<u16> = vec![Service::Watch as u16].into_iter().collect();
+        self.client.register(services).await?;
+
+        // print events
+        loop {
+            let event = match self.client.receive().await? {
+                Message::Event { event, .. } => event,
+                _ => continue,
+            };
+            if self.json {
+                self.print_json(&event);
+            } else {
+                self.print_text(event);
+            }
+        }
+    }
+}
+
+/// run watch client
+pub async fn run(config: config::Config) {
+    match api::Client::connect(config.dir.clone().unwrap()).await {
+        Ok(client) => {
+            if let Err(e) = WatchClient::new(config, client).await.run().await {
+                error!("{}", e);
+            }
+        }
+        Err(e) => error!("unix socket client error: {}", e),
+    }
+    debug!("watch client stopped");
+}
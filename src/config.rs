@@ -15,21 +15,19 @@ impl FromStr for ConfigOption {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // parse option string and get name and value
-        let parts: Vec<&str> = s.split(":").collect();
-        if parts.len() < 1 || parts.len() > 2 {
+        // parse option string and get name and value, the value may
+        // contain ":" itself, e.g., in IPv6 addresses
+        let (name, value) = match s.split_once(":") {
+            // only name given?
+            None => (s, ""),
+            // name and value given
+            Some((name, value)) => (name, value),
+        };
+        if name.is_empty() {
             return Err(String::from("invalid configuration option"));
         }
-        let name = String::from(parts[0]);
-
-        // only name given?
-        if parts.len() == 1 {
-            let value = String::from("");
-            return Ok(ConfigOption { name, value });
-        }
-
-        // name and value given
-        let value = String::from(parts[1]);
+        let name = String::from(name);
+        let value = String::from(value);
         Ok(ConfigOption { name, value })
     }
 }
@@ -69,6 +67,32 @@ pub struct ChatOpts {
     pub name: Option<String>,
 }
 
+#[derive(Clone, Parser)]
+#[clap(version)]
+pub struct PingOpts {
+    /// Peer ID of the peer to ping
+    pub peer: String,
+
+    /// Stop after sending count pings
+    #[clap(long, short)]
+    pub count: Option<u32>,
+}
+
+#[derive(Clone, Parser)]
+#[clap(version)]
+pub struct TraceOpts {
+    /// Peer ID of the peer to trace
+    pub peer: String,
+}
+
+#[derive(Clone, Parser)]
+#[clap(version)]
+pub struct WatchOpts {
+    /// Print events as JSON lines
+    #[clap(long)]
+    pub json: bool,
+}
+
 #[derive(Clone, Parser)]
 pub enum Command {
     /// Run daemon
@@ -81,6 +105,12 @@ pub enum Command {
     Chat(ChatOpts),
     /// Run in file mode
     Files,
+    /// Measure round-trip time to a peer
+    Ping(PingOpts),
+    /// Show path of relayed messages to a peer
+    Trace(TraceOpts),
+    /// Print daemon events as they happen
+    Watch(WatchOpts),
 }
 
 #[derive(Clone, Parser)]
@@ -95,14 +125,20 @@ pub struct Config {
     pub command: Option<Command>,
 }
 
+/// get default working directory
+pub fn default_dir() -> Option<PathBuf> {
+    let mut dir = dirs::config_dir()?;
+    dir.push("hi");
+    Some(dir)
+}
+
 /// get config
 pub fn get() -> Config {
     let mut config = Config::parse();
 
     // check working directory
     if let None = config.dir {
-        if let Some(mut dir) = dirs::config_dir() {
-            dir.push("hi");
+        if let Some(dir) = default_dir() {
             create_dir_all(&dir).expect("could not create directory");
             config.dir = Some(dir);
         } else {
@@ -1,78 +1,207 @@
+mod addresses;
 mod behaviour;
+mod fragment;
+mod gate;
 mod gossip;
+mod identity;
+mod outbox;
+mod relay;
 mod request;
 mod swarm;
 
 use crate::config;
+use crate::http_socket;
 use crate::message::{self, GetSet, Message, PeerInfo, Service};
+use crate::tcp_socket;
 use crate::unix_socket;
 use futures::channel::mpsc;
 use futures::future::FutureExt;
 use futures::sink::SinkExt;
 use futures::StreamExt;
+use libp2p::PeerId;
 use std::collections::hash_map::{Entry, HashMap};
 use std::collections::HashSet;
+use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::time::{self, Duration, Instant};
 
+/// timeout of trace requests
+const TRACE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// time to wait for the acknowledgement of a relayed message
+const RELAY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// default time in seconds after which peers without announcements are
+/// removed
+const DEFAULT_PEER_TIMEOUT: u64 = 30;
+
+/// minimum time in seconds after which peers without announcements are
+/// removed, peers announce themselves every 5 seconds, so a peer may miss
+/// two announcements before it is removed
+const MIN_PEER_TIMEOUT: u64 = 15;
+
 type Sender<T> = mpsc::UnboundedSender<T>;
 type Receiver<T> = mpsc::UnboundedReceiver<T>;
 
 /// Daemon events
 enum Event {
-    AddClient(u16, Sender<Message>),
+    AddClient(u16, Sender<Message>, unix_socket::Credentials),
     RemoveClient(u16),
     ClientMessage(u16, Message),
 }
 
+/// Connection information of a peer coming from the swarm
+#[derive(Default)]
+struct PeerConnection {
+    connected: bool,
+    agent_version: String,
+    protocol_version: String,
+    listen_addrs: Vec<String>,
+    observed_addr: String,
+    rtt: Option<u64>,
+    request_versions: Vec<String>,
+}
+
+impl PeerConnection {
+    /// copy connection information into `peer_info`
+    fn update(&self, peer_info: &mut PeerInfo) {
+        peer_info.connected = self.connected;
+        peer_info.agent_version = self.agent_version.clone();
+        peer_info.protocol_version = self.protocol_version.clone();
+        peer_info.listen_addrs = self.listen_addrs.clone();
+        peer_info.observed_addr = self.observed_addr.clone();
+        peer_info.rtt = self.rtt;
+        peer_info.request_versions = self.request_versions.clone();
+        check_versions(peer_info);
+    }
+}
+
+/// check if protocol versions of peer `peer_info` match our versions
+fn check_versions(peer_info: &mut PeerInfo) {
+    let current = request::REQUEST_PROTOCOL_VERSIONS[0].to_string();
+    peer_info.version_mismatch = peer_info.version != gossip::ANNOUNCE_VERSION
+        || (!peer_info.request_versions.is_empty()
+            && !peer_info.request_versions.contains(&current));
+}
+
+/// Message sent to another peer that waits for its result
+struct SentMessage {
+    message: outbox::OutboxMessage,
+    /// start time of relayed messages that wait for the acknowledgement of
+    /// the destination
+    relayed: Option<Instant>,
+}
+
 /// Client information
 struct ClientInfo {
     sender: Sender<Message>,
     services: HashSet<u16>,
+    topics: HashSet<String>,
+    uid: u32,
+    pid: Option<i32>,
 }
 
 /// Daemon
 struct Daemon {
     config: config::Config,
     server: unix_socket::UnixServer,
+    policy: unix_socket::ClientPolicy,
     from_client_rx: Receiver<Event>,
     from_client_tx: Sender<Event>,
     swarm: swarm::HiSwarm,
     client_id: u16,
     clients: HashMap<u16, ClientInfo>,
     peers: HashMap<String, PeerInfo>,
+    connections: HashMap<String, PeerConnection>,
     name: String,
+    peer_id: String,
+    addresses: Vec<String>,
+    routes: relay::Routes,
+    outbox: outbox::Outbox,
+    /// time in seconds after which peers without announcements are removed
+    peer_timeout: u64,
+    /// pending trace requests: client, request id, destination peer, start time
+    traces: HashMap<u32, (u16, u32, String, Instant)>,
+    trace_id: u32,
+    /// messages sent to other peers waiting for their results
+    sent: HashMap<u32, SentMessage>,
+    /// id of the next sent message, 0 is used for messages without result,
+    /// peers drop retransmitted messages with the same id
+    send_id: u32,
 }
 
 impl Daemon {
     pub async fn new(
         config: config::Config,
         server: unix_socket::UnixServer,
+        policy: unix_socket::ClientPolicy,
         swarm: swarm::HiSwarm,
+        outbox: outbox::Outbox,
+        peer_timeout: u64,
     ) -> Self {
         let (from_client_tx, from_client_rx) = mpsc::unbounded();
+        let peer_id = swarm.peer_id();
         Daemon {
             config,
             server,
+            policy,
             from_client_rx,
             from_client_tx,
             swarm,
             client_id: 1,
             clients: HashMap::new(),
             peers: HashMap::new(),
+            connections: HashMap::new(),
             name: String::new(),
+            peer_id,
+            addresses: Vec::new(),
+            routes: relay::Routes::default(),
+            outbox,
+            peer_timeout,
+            traces: HashMap::new(),
+            trace_id: 0,
+            sent: HashMap::new(),
+            // start with a random id so peers do not drop messages after
+            // a restart as duplicates
+            send_id: rand::random::<u32>().max(1),
         }
     }
 
-    /// handle client connection identified by its `id`
+    /// handle client connection identified by its `id`, only clients allowed
+    /// by `policy` are accepted
     async fn handle_client(
         mut server: Sender<Event>,
         id: u16,
         mut client: unix_socket::UnixClient,
+        owner: unix_socket::Credentials,
+        policy: unix_socket::ClientPolicy,
     ) {
+        // check credentials of the client before talking to it
+        let credentials = match client.peer_credentials() {
+            Ok(credentials) => credentials,
+            Err(e) => {
+                error!("error getting client credentials: {}", e);
+                return;
+            }
+        };
+        if !policy.allows(&owner, &credentials) {
+            error!(
+                "rejected client with uid {} and pid {:?}",
+                credentials.uid, credentials.pid
+            );
+            return;
+        }
+
+        // check handshake of the client
+        if let Err(e) = client.accept().await {
+            error!("client handshake error: {}", e);
+            return;
+        }
+
         // create channel for server messages and register this client
         let (client_sender, mut client_receiver) = mpsc::unbounded();
-        if let Err(e) = server.send(Event::AddClient(id, client_sender)).await {
+        let event = Event::AddClient(id, client_sender, credentials);
+        if let Err(e) = server.send(event).await {
             error!("handle client error: {}", e);
             return;
         }
@@ -128,6 +257,8 @@ impl Daemon {
             self.from_client_tx.clone(),
             self.client_id,
             client,
+            self.server.owner(),
+            self.policy.clone(),
         ));
 
         // update next client id
@@ -147,13 +278,54 @@ impl Daemon {
             .as_secs();
         let mut remove_peers = Vec::new();
         for peer in self.peers.values() {
-            if current_secs - peer.last_update > 30 {
+            if current_secs.saturating_sub(peer.last_update) > self.peer_timeout {
                 remove_peers.push(peer.peer_id.clone());
             }
         }
         for peer in remove_peers {
             self.peers.remove(&peer);
+            self.routes.remove(&peer);
+            let event = message::Event::PeerRemoved(peer);
+            self.send_daemon_event(event, 0).await;
+        }
+
+        // fail expired messages in the outbox
+        for message in self.outbox.expire().await {
+            debug!("message {} in outbox expired", message.id);
+            let result = Err(message::DeliveryError::Timeout);
+            self.report_message_result(message, result).await;
+        }
+
+        // fail trace requests that timed out
+        let expired: Vec<u32> = self
+            .traces
+            .iter()
+            .filter(|(_, (.., start))| start.elapsed() > TRACE_TIMEOUT)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            if let Some((client_id, request_id, ..)) = self.traces.remove(&id) {
+                let content = GetSet::Error(String::from("trace timed out"));
+                self.send_get_reply(client_id, request_id, content).await;
+            }
+        }
+
+        // fail relayed messages without acknowledgement
+        let expired: Vec<u32> = self
+            .sent
+            .iter()
+            .filter(|(_, sent)| sent.relayed.is_some_and(|s| s.elapsed() > RELAY_TIMEOUT))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            let result = Err(message::DeliveryError::Timeout);
+            self.handle_sent_result(id, result).await;
         }
+
+        // remove connection information of unknown and disconnected peers
+        let peers = &self.peers;
+        self.connections
+            .retain(|peer, connection| connection.connected || peers.contains_key(peer));
     }
 
     /// handle "announce peer" swarm event
@@ -162,18 +334,32 @@ impl Daemon {
         peer_id: String,
         name: String,
         services_tag: u32,
+        version: u8,
+        neighbors: Vec<String>,
     ) {
+        // update routes through this peer
+        self.routes.update(peer_id.clone(), neighbors);
+
         // add or update peer entry
         // TODO: check/update services
-        let peer_info = PeerInfo {
+        let mut peer_info = PeerInfo {
             peer_id,
             name,
             services_tag,
+            version,
             last_update: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .expect("timestamp error")
                 .as_secs(),
+            ..Default::default()
         };
+        check_versions(&mut peer_info);
+        if let Some(connection) = self.connections.get(&peer_info.peer_id) {
+            connection.update(&mut peer_info);
+        }
+        if peer_info.version_mismatch {
+            debug!("peer {} has different protocol versions", peer_info.peer_id);
+        }
         match self.peers.entry(peer_info.peer_id.clone()) {
             Entry::Occupied(mut entry) => {
                 entry.insert(peer_info.clone());
@@ -183,17 +369,115 @@ impl Daemon {
             }
         }
 
-        // forward peer info to service clients
-        for (id, client) in self.clients.iter_mut() {
-            if client.services.contains(&(Service::Service as u16)) {
-                let msg = Message::Event {
-                    to_client: *id,
-                    from_client: 0,
-                    event: message::Event::PeerUpdate(peer_info.clone()),
-                };
-                if let Err(e) = client.sender.send(msg).await {
-                    error!("handle client error: {}", e);
-                }
+        // send messages waiting in the outbox for this peer
+        for message in self.outbox.take(&peer_info.peer_id).await {
+            debug!("sending message {} from outbox", message.id);
+            self.send_peer_message(message).await;
+        }
+
+        // forward peer info to service and watch clients
+        let event = message::Event::PeerUpdate(peer_info.clone());
+        self.send_daemon_event(event, 0).await;
+    }
+
+    /// handle "peer identified" swarm event
+    async fn handle_swarm_peer_identified(
+        &mut self,
+        peer_id: String,
+        agent_version: String,
+        protocol_version: String,
+        listen_addrs: Vec<String>,
+        observed_addr: String,
+        request_versions: Vec<String>,
+    ) {
+        let connection = self.connections.entry(peer_id.clone()).or_default();
+        connection.agent_version = agent_version;
+        connection.protocol_version = protocol_version;
+        connection.listen_addrs = listen_addrs;
+        connection.observed_addr = observed_addr;
+        connection.request_versions = request_versions;
+        if let Some(peer_info) = self.peers.get_mut(&peer_id) {
+            connection.update(peer_info);
+        }
+    }
+
+    /// handle "peer connection" swarm event
+    async fn handle_swarm_peer_connection(&mut self, peer_id: String, connected: bool) {
+        let connection = self.connections.entry(peer_id.clone()).or_default();
+        let changed = connection.connected != connected;
+        connection.connected = connected;
+        if let Some(peer_info) = self.peers.get_mut(&peer_id) {
+            connection.update(peer_info);
+        }
+
+        // notify service and watch clients
+        if changed {
+            let event = message::Event::PeerConnection(peer_id, connected);
+            self.send_daemon_event(event, 0).await;
+        }
+    }
+
+    /// handle "peer rtt" swarm event
+    async fn handle_swarm_peer_rtt(&mut self, peer_id: String, rtt: u64) {
+        let connection = self.connections.entry(peer_id.clone()).or_default();
+        connection.rtt = Some(rtt);
+        if let Some(peer_info) = self.peers.get_mut(&peer_id) {
+            connection.update(peer_info);
+        }
+    }
+
+    /// handle "ping result" swarm event
+    async fn handle_swarm_ping_result(
+        &mut self,
+        client_id: u16,
+        request_id: u32,
+        peer_id: String,
+        result: Result<u64, String>,
+    ) {
+        let content = match result {
+            Ok(rtt) => GetSet::Ping(peer_id, rtt),
+            Err(e) => GetSet::Error(e),
+        };
+        self.send_get_reply(client_id, request_id, content).await;
+    }
+
+    /// send reply to get request `request_id` of client `client_id`
+    async fn send_get_reply(&mut self, client_id: u16, request_id: u32, content: GetSet) {
+        let reply = Message::Get {
+            client_id,
+            request_id,
+            content,
+        };
+        if let Some(client) = self.clients.get_mut(&client_id) {
+            if let Err(e) = client.sender.send(reply).await {
+                error!("handle client error: {}", e);
+            }
+        }
+    }
+
+    /// handle "message result" swarm event
+    async fn handle_swarm_message_result(
+        &mut self,
+        client_id: u16,
+        message_id: u32,
+        peer_id: String,
+        result: Result<(), message::DeliveryError>,
+    ) {
+        let event = match result {
+            Ok(()) => message::Event::MessageDelivered(message_id, peer_id),
+            Err(e) => {
+                debug!("message {} to {} failed: {}", message_id, peer_id, e);
+                message::Event::MessageFailed(message_id, peer_id, e)
+            }
+        };
+        let msg = Message::Event {
+            to_client: client_id,
+            from_client: 0,
+            event,
+        };
+        if let Some(client) = self.clients.get_mut(&client_id) {
+            if let Err(e) = client.sender.send(msg).await {
+                error!("handle client error: {}", e);
             }
         }
     }
@@ -223,6 +507,7 @@ impl Daemon {
                 from_client,
                 service,
                 content,
+                message_id: 0,
             };
             if let Err(e) = client.sender.send(msg).await {
                 error!("handle client error: {}", e);
@@ -230,9 +515,14 @@ impl Daemon {
             }
         }
 
-        // handle message to all clients
+        // handle message to all clients, skip the source client of local
+        // messages
         if to_client == Message::ALL_CLIENTS {
-            for client in self.clients.values_mut() {
+            let local = from_peer == self.peer_id;
+            for (id, client) in self.clients.iter_mut() {
+                if local && *id == from_client {
+                    continue;
+                }
                 if client.services.contains(&service) {
                     send(
                         client,
@@ -266,12 +556,123 @@ impl Daemon {
         }
     }
 
+    /// get directly connected peers that can relay messages
+    fn relay_peers(&self) -> HashSet<String> {
+        self.connections
+            .iter()
+            .filter(|(_, connection)| {
+                connection.connected
+                    && connection
+                        .request_versions
+                        .iter()
+                        .any(|v| v == request::RELAY_VERSION)
+            })
+            .map(|(peer, _)| peer.clone())
+            .collect()
+    }
+
+    /// get next hop on the way to `to_peer`, the peer itself if it is
+    /// connected directly
+    fn next_hop(&self, to_peer: &str) -> Option<String> {
+        if self.connections.get(to_peer).is_some_and(|c| c.connected) {
+            return Some(to_peer.to_string());
+        }
+        self.relay_next_hop(to_peer)
+    }
+
+    /// get next hop of relay messages on the way to `to_peer`, only peers
+    /// that support relay messages are used
+    fn relay_next_hop(&self, to_peer: &str) -> Option<String> {
+        let path = self
+            .routes
+            .find(&self.peer_id, &self.relay_peers(), to_peer)?;
+        path.into_iter().next()
+    }
+
+    /// send relay message `relay` to its next hop, try sending it directly
+    /// to the destination if there is no known route
+    async fn send_relay(&mut self, relay: relay::RelayMessage) {
+        let next_hop = self
+            .relay_next_hop(&relay.to_peer)
+            .unwrap_or_else(|| relay.to_peer.clone());
+        let event = swarm::Event::SendRelay(next_hop, relay, 0, 0);
+        self.swarm.send(event).await;
+    }
+
+    /// handle "relay" swarm event: deliver relay message or forward it
+    /// to the next hop
+    async fn handle_swarm_relay(&mut self, from_peer: String, mut relay: relay::RelayMessage) {
+        debug!(
+            "received relay message from {} to {} via {}",
+            relay.from_peer, relay.to_peer, from_peer
+        );
+
+        // forward relay message to next hop
+        if relay.to_peer != self.peer_id {
+            let next_hop = match self.relay_next_hop(&relay.to_peer) {
+                Some(next_hop) => next_hop,
+                None => {
+                    debug!("no route to {}, dropping relay message", relay.to_peer);
+                    return;
+                }
+            };
+            if relay.path.contains(&next_hop) {
+                debug!("routing loop to {}, dropping relay message", relay.to_peer);
+                return;
+            }
+            relay.path.push(self.peer_id.clone());
+            relay.hops -= 1;
+            let event = swarm::Event::SendRelay(next_hop, relay, 0, 0);
+            self.swarm.send(event).await;
+            return;
+        }
+
+        // handle relay message for this node
+        match relay.content {
+            relay::RelayContent::Message(to_client, from_client, service, content, id) => {
+                self.handle_swarm_message(
+                    relay.from_peer.clone(),
+                    from_client,
+                    to_client,
+                    service,
+                    content,
+                )
+                .await;
+                let content = relay::RelayContent::Ack(id);
+                let ack = relay::RelayMessage::new(self.peer_id.clone(), relay.from_peer, content);
+                self.send_relay(ack).await;
+            }
+            relay::RelayContent::TraceRequest(id) => {
+                let mut path = relay.path;
+                path.push(self.peer_id.clone());
+                let content = relay::RelayContent::TraceReply(id, path);
+                let reply =
+                    relay::RelayMessage::new(self.peer_id.clone(), relay.from_peer, content);
+                self.send_relay(reply).await;
+            }
+            relay::RelayContent::TraceReply(id, path) => {
+                if let Some((client_id, request_id, peer_id, _)) = self.traces.remove(&id) {
+                    let content = GetSet::Trace(peer_id, path);
+                    self.send_get_reply(client_id, request_id, content).await;
+                }
+            }
+            relay::RelayContent::Ack(id) => {
+                let acknowledged = self.sent.get(&id).is_some_and(|sent| {
+                    sent.relayed.is_some() && sent.message.to_peer == relay.from_peer
+                });
+                if acknowledged {
+                    self.handle_sent_result(id, Ok(())).await;
+                }
+            }
+        }
+    }
+
     /// handle swarm event
     async fn handle_swarm_event(&mut self, event: swarm::Event) {
         match event {
             // handle peer announcement
-            swarm::Event::AnnouncePeer(peer_id, name, services_tag) => {
-                self.handle_swarm_announce_peer(peer_id, name, services_tag)
+            swarm::Event::AnnouncePeer(peer_id, name, services_tag, version, neighbors) => {
+                self.handle_swarm_announce_peer(peer_id, name, services_tag, version, neighbors)
                     .await;
             }
 
@@ -281,13 +682,111 @@ impl Daemon {
                     .await;
             }
 
+            // handle relayed messages
+            swarm::Event::Relay(from_peer, relay) => {
+                self.handle_swarm_relay(from_peer, relay).await;
+            }
+
+            // handle messages on client topics
+            swarm::Event::TopicMessage(topic, from_peer, content) => {
+                self.send_topic_message(None, &topic, from_peer, &content)
+                    .await;
+            }
+
+            // handle peer connection information
+            swarm::Event::PeerIdentified(
+                peer_id,
+                agent_version,
+                protocol_version,
+                listen_addrs,
+                observed_addr,
+                request_versions,
+            ) => {
+                self.handle_swarm_peer_identified(
+                    peer_id,
+                    agent_version,
+                    protocol_version,
+                    listen_addrs,
+                    observed_addr,
+                    request_versions,
+                )
+                .await;
+            }
+            swarm::Event::PeerConnection(peer_id, connected) => {
+                self.handle_swarm_peer_connection(peer_id, connected).await;
+            }
+            swarm::Event::PeerRtt(peer_id, rtt) => {
+                self.handle_swarm_peer_rtt(peer_id, rtt).await;
+            }
+
+            // handle ping results
+            swarm::Event::PingResult(client_id, request_id, peer_id, result) => {
+                self.handle_swarm_ping_result(client_id, request_id, peer_id, result)
+                    .await;
+            }
+
+            // handle allowed and blocked peers of the connection gate
+            swarm::Event::GatePeers(client_id, request_id, blocked, peers) => {
+                let content = if blocked {
+                    GetSet::Blocked(peers)
+                } else {
+                    GetSet::Allowed(peers)
+                };
+                self.send_get_reply(client_id, request_id, content).await;
+            }
+
+            // handle publish results
+            swarm::Event::PublishResult(client_id, result) => {
+                let reply = match result {
+                    Ok(()) => Message::Ok,
+                    Err(message) => Message::Error { message },
+                };
+                if let Some(client) = self.clients.get_mut(&client_id) {
+                    if let Err(e) = client.sender.send(reply).await {
+                        error!("handle client error: {}", e);
+                    }
+                }
+            }
+
+            // handle message results
+            swarm::Event::MessageResult(client_id, message_id, peer_id, result) => {
+                // results of messages sent to other peers carry the id of
+                // the sent message, relayed messages are delivered when the
+                // destination acknowledges them
+                if peer_id != Message::ALL_PEERS {
+                    if let Some(sent) = self.sent.get(&message_id) {
+                        if result.is_err() || sent.relayed.is_none() {
+                            self.handle_sent_result(message_id, result).await;
+                        }
+                        return;
+                    }
+                }
+                self.handle_swarm_message_result(client_id, message_id, peer_id, result)
+                    .await;
+            }
+
+            // handle listen addresses
+            swarm::Event::NewListenAddress(address) => {
+                if !self.addresses.contains(&address) {
+                    self.addresses.push(address);
+                }
+            }
+            swarm::Event::ExpiredListenAddress(address) => {
+                self.addresses.retain(|a| *a != address);
+            }
+
             // handle other events
             _ => (),
         }
     }
 
     /// handle "add client" client event
-    async fn handle_client_add(&mut self, id: u16, sender: Sender<Message>) {
+    async fn handle_client_add(
+        &mut self,
+        id: u16,
+        sender: Sender<Message>,
+        credentials: unix_socket::Credentials,
+    ) {
         debug!("received add client event with id {}", id);
         match self.clients.entry(id) {
             Entry::Occupied(..) => (),
@@ -295,26 +794,66 @@ impl Daemon {
                 let client_info = ClientInfo {
                     sender,
                     services: HashSet::new(),
+                    topics: HashSet::new(),
+                    uid: credentials.uid,
+                    pid: credentials.pid,
                 };
                 entry.insert(client_info);
             }
         }
     }
 
+    /// subscribe to broadcast messages of all application services of local
+    /// clients, internal services like service and watch are local only
+    async fn update_service_subscriptions(&mut self) {
+        let services = self
+            .clients
+            .values()
+            .flat_map(|client| client.services.iter().cloned())
+            .filter(|service| *service >= Service::Chat as u16)
+            .collect();
+        let event = swarm::Event::SubscribeServices(services);
+        self.swarm.send(event).await;
+    }
+
+    /// subscribe to all client topics of local clients
+    async fn update_topic_subscriptions(&mut self) {
+        let topics = self
+            .clients
+            .values()
+            .flat_map(|client| client.topics.iter().cloned())
+            .collect();
+        let event = swarm::Event::SubscribeTopics(topics);
+        self.swarm.send(event).await;
+    }
+
     /// handle "remove client" client event
     async fn handle_client_remove(&mut self, id: u16) {
         debug!("received remove client event with id {}", id);
         self.clients.remove(&id);
+        self.update_service_subscriptions().await;
+        self.update_topic_subscriptions().await;
+
+        // send event to service and watch clients
+        let event = message::Event::ClientUpdate(false, id, HashSet::new());
+        self.send_daemon_event(event, 0).await;
+    }
 
-        // send event to service client
+    /// send daemon `event` to service and watch clients except client `skip`
+    async fn send_daemon_event(&mut self, event: message::Event, skip: u16) {
         for (client_id, client) in self.clients.iter_mut() {
-            if client.services.contains(&(Service::Service as u16)) {
-                let event = Message::Event {
+            if *client_id == skip {
+                continue;
+            }
+            if client.services.contains(&(Service::Service as u16))
+                || client.services.contains(&(Service::Watch as u16))
+            {
+                let msg = Message::Event {
                     from_client: 0,
                     to_client: *client_id,
-                    event: message::Event::ClientUpdate(false, id, HashSet::new()),
+                    event: event.clone(),
                 };
-                if let Err(e) = client.sender.send(event).await {
+                if let Err(e) = client.sender.send(msg).await {
                     error!("handle client error: {}", e);
                 }
             }
@@ -335,24 +874,12 @@ impl Daemon {
                 };
             }
         };
+        self.update_service_subscriptions().await;
 
-        // send event to service client
-        for (client_id, client) in self.clients.iter_mut() {
-            if *client_id == id {
-                // do not send own event to registering client
-                continue;
-            }
-            if client.services.contains(&(Service::Service as u16)) {
-                let event = Message::Event {
-                    from_client: 0,
-                    to_client: *client_id,
-                    event: message::Event::ClientUpdate(true, id, services.clone()),
-                };
-                if let Err(e) = client.sender.send(event).await {
-                    error!("handle client error: {}", e);
-                }
-            }
-        }
+        // send event to service and watch clients, but not to the
+        // registering client
+        let event = message::Event::ClientUpdate(true, id, services);
+        self.send_daemon_event(event, id).await;
 
         // reply with client id
         Message::RegisterOk { client_id: id }
@@ -368,6 +895,20 @@ impl Daemon {
         let content = match content {
             GetSet::Name(..) => GetSet::Name(self.name.clone()),
             GetSet::Peers(..) => GetSet::Peers(self.peers.values().cloned().collect()),
+            GetSet::PeerId(..) => GetSet::PeerId(self.peer_id.clone()),
+            GetSet::Addresses(..) => GetSet::Addresses(self.addresses.clone()),
+            GetSet::Outbox(..) => GetSet::Outbox(self.outbox.list()),
+            GetSet::Clients(..) => GetSet::Clients(
+                self.clients
+                    .iter()
+                    .map(|(client_id, client)| message::ClientInfo {
+                        client_id: *client_id,
+                        uid: client.uid,
+                        pid: client.pid,
+                        services: client.services.iter().cloned().collect(),
+                    })
+                    .collect(),
+            ),
             _ => GetSet::Error(String::from("Unknown get request")),
         };
         Message::Get {
@@ -377,6 +918,71 @@ impl Daemon {
         }
     }
 
+    /// handle "ping" get client message event, reply is sent when the
+    /// swarm reports the result of the ping
+    async fn handle_client_ping(&mut self, client_id: u16, request_id: u32, peer_id: String) {
+        let event = swarm::Event::Ping(peer_id, client_id, request_id);
+        self.swarm.send(event).await;
+    }
+
+    /// handle "blocked" and "allowed" get client message events, reply is
+    /// sent when the swarm reports the peers of the connection gate
+    async fn handle_client_get_gate(&mut self, client_id: u16, request_id: u32, blocked: bool) {
+        let event = swarm::Event::GetGatePeers(client_id, request_id, blocked);
+        self.swarm.send(event).await;
+    }
+
+    /// handle "trace" get client message event, reply is sent when the
+    /// trace reply of the peer arrives or the trace times out
+    async fn handle_client_trace(&mut self, client_id: u16, request_id: u32, peer_id: String) {
+        if PeerId::from_str(&peer_id).is_err() {
+            let content = GetSet::Error(String::from("invalid peer id"));
+            self.send_get_reply(client_id, request_id, content).await;
+            return;
+        }
+        if peer_id == self.peer_id {
+            let content = GetSet::Trace(peer_id, vec![self.peer_id.clone()]);
+            self.send_get_reply(client_id, request_id, content).await;
+            return;
+        }
+
+        // send trace request through the relays to the peer
+        let id = self.trace_id;
+        self.trace_id = self.trace_id.wrapping_add(1);
+        self.traces
+            .insert(id, (client_id, request_id, peer_id.clone(), Instant::now()));
+        let content = relay::RelayContent::TraceRequest(id);
+        let relay = relay::RelayMessage::new(self.peer_id.clone(), peer_id, content);
+        self.send_relay(relay).await;
+    }
+
+    /// handle allow and deny list changes of "set" client message event
+    async fn handle_client_set_gate(&mut self, content: GetSet) -> GetSet {
+        let peer_id = match content {
+            GetSet::Block(ref peer_id)
+            | GetSet::Unblock(ref peer_id)
+            | GetSet::Allow(ref peer_id)
+            | GetSet::Disallow(ref peer_id) => peer_id.clone(),
+            _ => return GetSet::Error(String::from("Unknown set request")),
+        };
+        if PeerId::from_str(&peer_id).is_err() {
+            return GetSet::Error(String::from("invalid peer id"));
+        }
+
+        let event = match content {
+            GetSet::Block(..) => {
+                self.peers.remove(&peer_id);
+                self.routes.remove(&peer_id);
+                swarm::Event::BlockPeer(peer_id)
+            }
+            GetSet::Unblock(..) => swarm::Event::UnblockPeer(peer_id),
+            GetSet::Allow(..) => swarm::Event::AllowPeer(peer_id),
+            _ => swarm::Event::DisallowPeer(peer_id),
+        };
+        self.swarm.send(event).await;
+        GetSet::Ok
+    }
+
     /// handle "set" client message event
     async fn handle_client_set(
         &mut self,
@@ -401,6 +1007,28 @@ impl Daemon {
                 self.swarm.send(event).await;
                 GetSet::Ok
             }
+            GetSet::Listen(address) => {
+                let event = swarm::Event::ListenAddress(address);
+                self.swarm.send(event).await;
+                GetSet::Ok
+            }
+            GetSet::Unlisten(address) => {
+                let event = swarm::Event::UnlistenAddress(address);
+                self.swarm.send(event).await;
+                GetSet::Ok
+            }
+            GetSet::Block(..) | GetSet::Unblock(..) | GetSet::Allow(..) | GetSet::Disallow(..) => {
+                self.handle_client_set_gate(content).await
+            }
+            GetSet::PurgeOutbox(peer_id) => {
+                for message in self.outbox.purge(&peer_id).await {
+                    let result = Err(message::DeliveryError::Other(String::from(
+                        "purged from outbox",
+                    )));
+                    self.report_message_result(message, result).await;
+                }
+                GetSet::Ok
+            }
             _ => GetSet::Error(String::from("Unknown set request")),
         };
         Message::Set {
@@ -418,14 +1046,218 @@ impl Daemon {
         from_client: u16,
         service: u16,
         content: Vec<u8>,
+        message_id: u32,
     ) -> Message {
-        debug!("received message for {}", to_peer);
+        debug!("received message {} for {}", message_id, to_peer);
+
+        // broadcast message to all peers and clients on this node
+        if to_peer == Message::ALL_PEERS {
+            let from_peer = self.peer_id.clone();
+            self.handle_swarm_message(from_peer, from_client, to_client, service, content.clone())
+                .await;
+            let event =
+                swarm::Event::Broadcast(to_client, from_client, service, content, message_id);
+            self.swarm.send(event).await;
+            return Message::Ok;
+        }
+
+        // deliver message to clients on this node
+        if to_peer.is_empty() || to_peer == self.peer_id {
+            let from_peer = self.peer_id.clone();
+            self.handle_swarm_message(from_peer.clone(), from_client, to_client, service, content)
+                .await;
+            self.handle_swarm_message_result(from_client, message_id, from_peer, Ok(()))
+                .await;
+            return Message::Ok;
+        }
+
+        // send message to another peer, result is reported to the client
+        let message = outbox::OutboxMessage::new(
+            to_peer,
+            to_client,
+            from_client,
+            service,
+            content,
+            message_id,
+        );
+        self.send_peer_message(message).await;
+        Message::Ok
+    }
+
+    /// check if messages for `to_peer` can be kept in the outbox
+    fn can_queue(&self, to_peer: &str) -> bool {
+        self.outbox.is_enabled() && PeerId::from_str(to_peer).is_ok()
+    }
+
+    /// keep `message` in the outbox until its destination peer is
+    /// announced again
+    async fn queue_message(&mut self, message: outbox::OutboxMessage) {
+        let (to_peer, message_id) = (message.to_peer.clone(), message.message_id);
+        match self.outbox.add(message.clone()).await {
+            Ok(()) => debug!("queued message {} for {} in outbox", message_id, to_peer),
+            Err(e) => {
+                let result = Err(message::DeliveryError::Other(e));
+                self.report_message_result(message, result).await;
+            }
+        }
+    }
+
+    /// send `message` to another peer directly or through relays, the
+    /// result is reported to the client
+    async fn send_peer_message(&mut self, message: outbox::OutboxMessage) {
+        let next_hop = self.next_hop(&message.to_peer);
+
+        // keep message for unknown and unreachable peer in the outbox
+        if next_hop.is_none()
+            && !self.peers.contains_key(&message.to_peer)
+            && self.can_queue(&message.to_peer)
+        {
+            self.queue_message(message).await;
+            return;
+        }
+
+        let id = self.send_id;
+        self.send_id = self.send_id.checked_add(1).unwrap_or(1);
+
+        // relay message through other peers if the destination is only
+        // reachable via another peer, the message is delivered when the
+        // destination acknowledges it
+        if let Some(next_hop) = next_hop.filter(|hop| *hop != message.to_peer) {
+            let content = relay::RelayContent::Message(
+                message.to_client,
+                message.from_client,
+                message.service,
+                message.content.clone(),
+                id,
+            );
+            let to_peer = message.to_peer.clone();
+            let relay = relay::RelayMessage::new(self.peer_id.clone(), to_peer, content);
+            let relayed = Some(Instant::now());
+            self.sent.insert(id, SentMessage { message, relayed });
+            let event = swarm::Event::SendRelay(next_hop, relay, 0, id);
+            self.swarm.send(event).await;
+            return;
+        }
+
         // send message to specific peer
-        let event = swarm::Event::SendMessage(to_peer, to_client, from_client, service, content);
+        let event = swarm::Event::SendMessage(
+            message.to_peer.clone(),
+            message.to_client,
+            message.from_client,
+            message.service,
+            message.content.clone(),
+            id,
+        );
+        let relayed = None;
+        self.sent.insert(id, SentMessage { message, relayed });
         self.swarm.send(event).await;
+    }
+
+    /// handle `result` of sent message `id`, keep the message in the outbox
+    /// if the peer was not reachable
+    async fn handle_sent_result(&mut self, id: u32, result: Result<(), message::DeliveryError>) {
+        let sent = match self.sent.remove(&id) {
+            Some(sent) => sent,
+            None => return,
+        };
+        match result {
+            Err(ref e) if outbox::can_keep(e) && self.can_queue(&sent.message.to_peer) => {
+                self.queue_message(sent.message).await;
+            }
+            result => self.report_message_result(sent.message, result).await,
+        }
+    }
+
+    /// report `result` of `message` to its source client, results of
+    /// messages from a previous daemon run are dropped
+    async fn report_message_result(
+        &mut self,
+        message: outbox::OutboxMessage,
+        result: Result<(), message::DeliveryError>,
+    ) {
+        if message.is_restored() {
+            debug!("dropping result of message {} from outbox", message.id);
+            return;
+        }
+        self.handle_swarm_message_result(
+            message.from_client,
+            message.message_id,
+            message.to_peer,
+            result,
+        )
+        .await;
+    }
+
+    /// handle "subscribe" and "unsubscribe" client message event
+    async fn handle_client_subscribe(
+        &mut self,
+        id: u16,
+        topic: String,
+        subscribe: bool,
+    ) -> Message {
+        if let Err(message) = check_topic(&topic) {
+            return Message::Error { message };
+        }
+        if let Some(client) = self.clients.get_mut(&id) {
+            if subscribe {
+                client.topics.insert(topic);
+            } else {
+                client.topics.remove(&topic);
+            }
+        }
+        self.update_topic_subscriptions().await;
         Message::Ok
     }
 
+    /// handle "publish" client message event, return error reply for
+    /// invalid topics
+    async fn handle_client_publish(
+        &mut self,
+        id: u16,
+        topic: String,
+        content: Vec<u8>,
+    ) -> Option<Message> {
+        if let Err(message) = check_topic(&topic) {
+            return Some(Message::Error { message });
+        }
+
+        // deliver message to other subscribed clients on this node and
+        // publish it to other peers
+        let from_peer = self.peer_id.clone();
+        self.send_topic_message(Some(id), &topic, from_peer, &content)
+            .await;
+        let event = swarm::Event::Publish(id, topic, content);
+        self.swarm.send(event).await;
+        None
+    }
+
+    /// send message on `topic` to all subscribed clients except `skip`
+    async fn send_topic_message(
+        &mut self,
+        skip: Option<u16>,
+        topic: &str,
+        from_peer: String,
+        content: &[u8],
+    ) {
+        for (id, client) in self.clients.iter_mut() {
+            if Some(*id) == skip || !client.topics.contains(topic) {
+                continue;
+            }
+            let msg = Message::Event {
+                to_client: *id,
+                from_client: 0,
+                event: message::Event::TopicMessage(
+                    topic.to_string(),
+                    from_peer.clone(),
+                    content.to_vec(),
+                ),
+            };
+            if let Err(e) = client.sender.send(msg).await {
+                error!("handle client error: {}", e);
+            }
+        }
+    }
+
     /// handle "event" client message event
     async fn handle_client_event_msg(
         &mut self,
@@ -455,7 +1287,9 @@ impl Daemon {
     async fn handle_client_event(&mut self, event: Event) {
         match event {
             // handle add client
-            Event::AddClient(id, sender) => self.handle_client_add(id, sender).await,
+            Event::AddClient(id, sender, credentials) => {
+                self.handle_client_add(id, sender, credentials).await
+            }
 
             // handle remove client
             Event::RemoveClient(id) => self.handle_client_remove(id).await,
@@ -486,6 +1320,45 @@ impl Daemon {
                         self.handle_client_register(id, services).await
                     }
 
+                    // handle ping get message, reply is sent asynchronously
+                    Message::Get {
+                        request_id,
+                        content: GetSet::Ping(peer_id, ..),
+                        ..
+                    } => {
+                        self.handle_client_ping(id, request_id, peer_id).await;
+                        return;
+                    }
+
+                    // handle blocked and allowed get messages, reply is sent
+                    // asynchronously
+                    Message::Get {
+                        request_id,
+                        content: GetSet::Blocked(..),
+                        ..
+                    } => {
+                        self.handle_client_get_gate(id, request_id, true).await;
+                        return;
+                    }
+                    Message::Get {
+                        request_id,
+                        content: GetSet::Allowed(..),
+                        ..
+                    } => {
+                        self.handle_client_get_gate(id, request_id, false).await;
+                        return;
+                    }
+
+                    // handle trace get message, reply is sent asynchronously
+                    Message::Get {
+                        request_id,
+                        content: GetSet::Trace(peer_id, ..),
+                        ..
+                    } => {
+                        self.handle_client_trace(id, request_id, peer_id).await;
+                        return;
+                    }
+
                     // handle get message
                     Message::Get {
                         client_id,
@@ -507,6 +1380,7 @@ impl Daemon {
                         from_client,
                         service,
                         content,
+                        message_id,
                         ..
                     } => {
                         self.handle_client_message(
@@ -515,6 +1389,7 @@ impl Daemon {
                             from_client,
                             service,
                             content,
+                            message_id,
                         )
                         .await
                     }
@@ -530,6 +1405,25 @@ impl Daemon {
                         return;
                     }
 
+                    // handle subscribe message
+                    Message::Subscribe { topic } => {
+                        self.handle_client_subscribe(id, topic, true).await
+                    }
+
+                    // handle unsubscribe message
+                    Message::Unsubscribe { topic } => {
+                        self.handle_client_subscribe(id, topic, false).await
+                    }
+
+                    // handle publish message, reply is sent when the swarm
+                    // published the message
+                    Message::Publish { topic, content } => {
+                        match self.handle_client_publish(id, topic, content).await {
+                            Some(reply) => reply,
+                            None => return,
+                        }
+                    }
+
                     // handle other messages
                     Message::RegisterOk { .. } => return,
                 };
@@ -627,10 +1521,121 @@ impl Daemon {
     }
 }
 
+/// check if `topic` is a valid client topic name
+fn check_topic(topic: &str) -> Result<(), String> {
+    if topic.is_empty() || topic.len() > swarm::MAX_TOPIC_LENGTH {
+        return Err(format!("invalid topic: {:?}", topic));
+    }
+    Ok(())
+}
+
+/// get values of all daemon options with `name` from config
+fn get_options(config: &config::Config, name: &str) -> Vec<String> {
+    match config.command {
+        Some(config::Command::Daemon(ref daemon_opts)) => daemon_opts
+            .set
+            .iter()
+            .filter(|option| option.name == name)
+            .map(|option| option.value.clone())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// parse value of the last daemon option with `name` from config
+fn parse_option<T>(config: &config::Config, name: &str) -> Result<Option<T>, String>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match get_options(config, name).last() {
+        Some(value) => match value.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => Err(format!("error parsing {} option: {}", name, e)),
+        },
+        None => Ok(None),
+    }
+}
+
 /// entry point for running the daemon server
 pub async fn run(config: config::Config) {
+    // load node identity, create new one if requested
+    let regenerate = !get_options(&config, "new-peer-id").is_empty();
+    let keypair = match identity::load(&config, regenerate).await {
+        Ok(keypair) => keypair,
+        Err(e) => {
+            error!("error loading node identity: {}", e);
+            return;
+        }
+    };
+
+    // load pre-shared key if private network is enabled
+    let pnet = !get_options(&config, "pnet").is_empty();
+    let mut psk = None;
+    if pnet {
+        match identity::load_swarm_key(&config).await {
+            Ok(key) => psk = Some(key),
+            Err(e) => {
+                error!("error loading swarm key: {}", e);
+                return;
+            }
+        }
+    }
+
+    // get transports, use TCP by default, QUIC must be enabled with the
+    // transport option
+    let transport = match get_options(&config, "transport").last() {
+        Some(transport) => match transport.parse() {
+            Ok(transport) => transport,
+            Err(e) => {
+                error!("error parsing transport option: {}", e);
+                return;
+            }
+        },
+        None => swarm::Transport::Tcp,
+    };
+
+    // get maximum frame size, timeout in seconds and retransmissions of
+    // peer-to-peer messages
+    let max_frame_size = match parse_option(&config, "max-frame-size") {
+        Ok(size) => size.unwrap_or(request::DEFAULT_MAX_FRAME_SIZE),
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    let send_timeout = match parse_option(&config, "send-timeout") {
+        Ok(timeout) => timeout
+            .map(Duration::from_secs)
+            .unwrap_or(swarm::DEFAULT_SEND_TIMEOUT),
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    let send_retries = match parse_option(&config, "send-retries") {
+        Ok(retries) => retries.unwrap_or(swarm::DEFAULT_SEND_RETRIES),
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
     // create and run swarm
-    let swarm = match swarm::HiSwarm::run().await {
+    let options = swarm::Options {
+        keypair,
+        listen: get_options(&config, "listen"),
+        transport,
+        bootstrap: get_options(&config, "bootstrap"),
+        psk,
+        allow: get_options(&config, "allow"),
+        block: get_options(&config, "block"),
+        network: get_options(&config, "network").pop().unwrap_or_default(),
+        max_frame_size,
+        send_timeout,
+        send_retries,
+    };
+    let swarm = match swarm::HiSwarm::run(options).await {
         Ok(swarm) => swarm,
         Err(e) => {
             error!("error creating swarm: {}", e);
@@ -638,8 +1643,50 @@ pub async fn run(config: config::Config) {
         }
     };
 
+    // load outbox, time to live of messages in seconds, disabled if 0
+    let outbox_ttl = match parse_option(&config, "outbox-ttl") {
+        Ok(ttl) => ttl.unwrap_or(outbox::DEFAULT_OUTBOX_TTL),
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    let outbox = match outbox::Outbox::load(&config, outbox_ttl).await {
+        Ok(outbox) => outbox,
+        Err(e) => {
+            error!("error loading outbox: {}", e);
+            return;
+        }
+    };
+
+    // get time in seconds after which peers without announcements are
+    // removed
+    let peer_timeout = match parse_option(&config, "peer-timeout") {
+        Ok(timeout) => timeout.unwrap_or(DEFAULT_PEER_TIMEOUT),
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    if peer_timeout < MIN_PEER_TIMEOUT {
+        error!(
+            "peer timeout {} too small, minimum is {} seconds",
+            peer_timeout, MIN_PEER_TIMEOUT
+        );
+        return;
+    }
+
+    // get policy for local clients: owner, group or list of user ids
+    let policy = match parse_option(&config, "clients") {
+        Ok(policy) => policy.unwrap_or(unix_socket::ClientPolicy::Owner),
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
     // create unix server
-    let server = match unix_socket::UnixServer::listen(&config).await {
+    let server = match unix_socket::UnixServer::listen(&config, &policy).await {
         Ok(server) => server,
         Err(e) => {
             error!("unix socket server error: {}", e);
@@ -647,10 +1694,51 @@ pub async fn run(config: config::Config) {
         }
     };
 
+    // start tcp server on loopback address if requested
+    match parse_option(&config, "tcp-api") {
+        Ok(Some(address)) => match tcp_socket::TcpServer::listen(&config, address).await {
+            Ok(server) => {
+                tokio::spawn(server.run());
+            }
+            Err(e) => {
+                error!("tcp socket server error: {}", e);
+                return;
+            }
+        },
+        Ok(None) => (),
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    }
+
+    // start http server, a port number only binds to localhost
+    if let Some(address) = get_options(&config, "http-api").last() {
+        let server = match http_socket::parse_address(address) {
+            Ok(address) => http_socket::HttpServer::listen(&config, address).await,
+            Err(e) => {
+                error!("error parsing http-api option: {}", e);
+                return;
+            }
+        };
+        match server {
+            Ok(server) => {
+                tokio::spawn(server.run());
+            }
+            Err(e) => {
+                error!("http server error: {}", e);
+                return;
+            }
+        }
+    }
+
     // start service client
     crate::client::service::run(config.clone()).await;
 
     // start daemon
-    Daemon::new(config, server, swarm).await.run().await;
+    Daemon::new(config, server, policy, swarm, outbox, peer_timeout)
+        .await
+        .run()
+        .await;
     debug!("daemon stopped");
 }
@@ -0,0 +1,94 @@
+use libp2p::core::transport::PortUse;
+use libp2p::core::{ConnectedPoint, Endpoint};
+use libp2p::swarm::{
+    dummy, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent,
+    THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+
+/// maximum number of stored addresses per peer
+const MAX_ADDRESSES: usize = 8;
+
+/// Address book with addresses of peers from previous connections and mdns,
+/// provides the addresses when peers are dialed on demand
+#[derive(Default)]
+pub struct AddressBook {
+    addresses: HashMap<PeerId, Vec<Multiaddr>>,
+}
+
+impl AddressBook {
+    /// add address `addr` of peer `peer`, most recent address first
+    pub fn add(&mut self, peer: PeerId, addr: Multiaddr) {
+        let addresses = self.addresses.entry(peer).or_default();
+        addresses.retain(|a| *a != addr);
+        addresses.insert(0, addr);
+        addresses.truncate(MAX_ADDRESSES);
+    }
+
+    /// check if there are known addresses of peer `peer`
+    pub fn contains(&self, peer: &PeerId) -> bool {
+        self.addresses.contains_key(peer)
+    }
+}
+
+impl NetworkBehaviour for AddressBook {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: Option<PeerId>,
+        _: &[Multiaddr],
+        _: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        let addresses = peer.and_then(|peer| self.addresses.get(&peer));
+        Ok(addresses.cloned().unwrap_or_default())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+        _: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        // remember addresses of successfully dialed peers
+        if let FromSwarm::ConnectionEstablished(connection) = event {
+            if let ConnectedPoint::Dialer { address, .. } = connection.endpoint {
+                self.add(connection.peer_id, address.clone());
+            }
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
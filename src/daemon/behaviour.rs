@@ -1,16 +1,28 @@
+use crate::daemon::addresses::AddressBook;
+use crate::daemon::gate::Gate;
 use crate::daemon::request::{HiCodec, HiRequest, HiResponse};
 use libp2p::gossipsub;
+use libp2p::identify;
+use libp2p::kad;
 use libp2p::mdns;
+use libp2p::ping;
 use libp2p::request_response;
 use libp2p::swarm::NetworkBehaviour;
+use std::convert::Infallible;
 
-/// Custom network behaviour with mdns, gossipsub, request-response
+/// Custom network behaviour with connection gate, address book, mdns,
+/// gossipsub, request-response, kademlia, identify, ping
 #[derive(NetworkBehaviour)]
 #[behaviour(to_swarm = "HiBehaviourEvent")]
 pub struct HiBehaviour {
+    pub gate: Gate,
+    pub addresses: AddressBook,
     pub request: request_response::Behaviour<HiCodec>,
     pub gossip: gossipsub::Behaviour,
     pub mdns: mdns::tokio::Behaviour,
+    pub kad: kad::Behaviour<kad::store::MemoryStore>,
+    pub identify: identify::Behaviour,
+    pub ping: ping::Behaviour,
 }
 
 #[derive(Debug)]
@@ -18,6 +30,9 @@ pub enum HiBehaviourEvent {
     RequestResponse(request_response::Event<HiRequest, HiResponse>),
     Gossipsub(gossipsub::Event),
     Mdns(mdns::Event),
+    Kademlia(kad::Event),
+    Identify(identify::Event),
+    Ping(ping::Event),
 }
 
 impl From<request_response::Event<HiRequest, HiResponse>> for HiBehaviourEvent {
@@ -37,3 +52,27 @@ impl From<mdns::Event> for HiBehaviourEvent {
         HiBehaviourEvent::Mdns(event)
     }
 }
+
+impl From<kad::Event> for HiBehaviourEvent {
+    fn from(event: kad::Event) -> Self {
+        HiBehaviourEvent::Kademlia(event)
+    }
+}
+
+impl From<identify::Event> for HiBehaviourEvent {
+    fn from(event: identify::Event) -> Self {
+        HiBehaviourEvent::Identify(event)
+    }
+}
+
+impl From<ping::Event> for HiBehaviourEvent {
+    fn from(event: ping::Event) -> Self {
+        HiBehaviourEvent::Ping(event)
+    }
+}
+
+impl From<Infallible> for HiBehaviourEvent {
+    fn from(event: Infallible) -> Self {
+        match event {}
+    }
+}
@@ -0,0 +1,255 @@
+use crate::daemon::request::HiRequest;
+use libp2p::PeerId;
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+
+/// space reserved for the encoded fragment header in a frame
+const FRAGMENT_OVERHEAD: usize = 64;
+
+/// smallest supported maximum frame size
+pub const MIN_FRAME_SIZE: usize = 1024;
+
+/// maximum number of fragments of a message
+const MAX_FRAGMENTS: usize = 65536;
+
+/// time after which incomplete messages are dropped
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// maximum number of incomplete messages of a peer
+const MAX_PEER_PARTIALS: usize = 16;
+
+/// maximum number of incomplete messages of all peers
+const MAX_PARTIALS: usize = 256;
+
+/// maximum size of buffered fragments of a peer
+const MAX_PEER_BYTES: usize = 128 * 1024 * 1024;
+
+/// maximum size of buffered fragments of all peers
+const MAX_BYTES: usize = 512 * 1024 * 1024;
+
+/// encode `request` and split it into fragments with id `id` if it does not
+/// fit into a frame with `max_frame_size`
+pub fn split(request: HiRequest, id: u32, max_frame_size: usize) -> Result<Vec<HiRequest>, String> {
+    let mut buffer = Vec::new();
+    if let Err(e) = minicbor::encode(&request, &mut buffer) {
+        return Err(format!("error encoding request message: {}", e));
+    }
+    if buffer.len() + FRAGMENT_OVERHEAD <= max_frame_size {
+        return Ok(vec![request]);
+    }
+
+    let chunks = buffer.chunks(max_frame_size - FRAGMENT_OVERHEAD);
+    if chunks.len() > MAX_FRAGMENTS {
+        return Err(format!("message size {} too big", buffer.len()));
+    }
+    let count = chunks.len() as u32;
+    Ok(chunks
+        .enumerate()
+        .map(|(index, chunk)| HiRequest::Fragment(id, index as u32, count, chunk.to_vec()))
+        .collect())
+}
+
+/// Incomplete message
+struct Partial {
+    parts: Vec<Option<Vec<u8>>>,
+    missing: u32,
+    /// buffered bytes including the list of parts
+    bytes: usize,
+    last_update: Instant,
+}
+
+/// Reassembly of fragmented requests from peers
+#[derive(Default)]
+pub struct Reassembly {
+    partials: HashMap<(PeerId, u32), Partial>,
+    /// buffered bytes of all incomplete messages
+    bytes: usize,
+}
+
+impl Reassembly {
+    /// add fragment `index` of `count` fragments of message `id` from `peer`,
+    /// return the reassembled request if the message is complete
+    pub fn add(
+        &mut self,
+        peer: PeerId,
+        id: u32,
+        index: u32,
+        count: u32,
+        data: Vec<u8>,
+    ) -> Result<Option<HiRequest>, String> {
+        if index >= count || count as usize > MAX_FRAGMENTS {
+            return Err(format!("invalid fragment {} of {}", index, count));
+        }
+
+        // start new message if the limits of the peer and all peers allow it
+        let key = (peer, id);
+        if !self.partials.contains_key(&key) {
+            let peer_partials = self.partials.keys().filter(|(p, _)| *p == peer).count();
+            if peer_partials >= MAX_PEER_PARTIALS || self.partials.len() >= MAX_PARTIALS {
+                return Err(String::from("too many incomplete messages"));
+            }
+            let partial = Partial {
+                parts: vec![None; count as usize],
+                missing: count,
+                bytes: count as usize * std::mem::size_of::<Option<Vec<u8>>>(),
+                last_update: Instant::now(),
+            };
+            self.bytes += partial.bytes;
+            self.partials.insert(key, partial);
+        }
+        if self.partials[&key].parts.len() != count as usize {
+            self.remove(&key);
+            return Err(format!("invalid fragment count {}", count));
+        }
+
+        // drop message if the buffered data of the peer or all peers is
+        // too big
+        let old_len = self.partials[&key].parts[index as usize]
+            .as_ref()
+            .map_or(0, Vec::len);
+        let peer_bytes: usize = self
+            .partials
+            .iter()
+            .filter(|((p, _), _)| *p == peer)
+            .map(|(_, partial)| partial.bytes)
+            .sum();
+        let added = data.len().saturating_sub(old_len);
+        if peer_bytes + added > MAX_PEER_BYTES || self.bytes + added > MAX_BYTES {
+            self.remove(&key);
+            return Err(String::from("too much buffered fragment data"));
+        }
+
+        let partial = self.partials.get_mut(&key).unwrap();
+        partial.bytes = partial.bytes + data.len() - old_len;
+        self.bytes = self.bytes + data.len() - old_len;
+        partial.last_update = Instant::now();
+        if partial.parts[index as usize].replace(data).is_none() {
+            partial.missing -= 1;
+        }
+        if partial.missing > 0 {
+            return Ok(None);
+        }
+
+        // message complete, decode request
+        let partial = self.remove(&key).unwrap();
+        let buffer: Vec<u8> = partial.parts.into_iter().flatten().flatten().collect();
+        match minicbor::decode(&buffer) {
+            Ok(HiRequest::Fragment(..)) => Err(String::from("nested fragments")),
+            Ok(request) => Ok(Some(request)),
+            Err(e) => Err(format!("error decoding fragmented request: {}", e)),
+        }
+    }
+
+    /// remove incomplete message with `key`
+    fn remove(&mut self, key: &(PeerId, u32)) -> Option<Partial> {
+        let partial = self.partials.remove(key)?;
+        self.bytes -= partial.bytes;
+        Some(partial)
+    }
+
+    /// drop incomplete messages that timed out
+    pub fn expire(&mut self) {
+        let bytes = &mut self.bytes;
+        self.partials.retain(|(peer, id), partial| {
+            if partial.last_update.elapsed() < REASSEMBLY_TIMEOUT {
+                return true;
+            }
+            debug!("dropping incomplete message {} from {:?}", id, peer);
+            *bytes -= partial.bytes;
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// reassemble `fragments` from `peer`
+    fn reassemble(
+        reassembly: &mut Reassembly,
+        peer: PeerId,
+        fragments: Vec<HiRequest>,
+    ) -> Result<Option<HiRequest>, String> {
+        let mut result = Ok(None);
+        for fragment in fragments {
+            result = match fragment {
+                HiRequest::Fragment(id, index, count, data) => {
+                    reassembly.add(peer, id, index, count, data)
+                }
+                _ => panic!("not a fragment"),
+            };
+        }
+        result
+    }
+
+    #[test]
+    fn split_small_request() {
+        let request = HiRequest::Message(1, 2, 3, vec![0; 100], Some(4));
+        let requests = split(request.clone(), 0, MIN_FRAME_SIZE).unwrap();
+        assert_eq!(requests, vec![request]);
+    }
+
+    #[test]
+    fn split_and_reassemble() {
+        let content: Vec<u8> = (0..10000).map(|i| i as u8).collect();
+        let request = HiRequest::Message(1, 2, 3, content, Some(4));
+        let mut fragments = split(request.clone(), 7, MIN_FRAME_SIZE).unwrap();
+        assert!(fragments.len() > 1);
+        for fragment in fragments.iter() {
+            let mut buffer = Vec::new();
+            minicbor::encode(fragment, &mut buffer).unwrap();
+            assert!(buffer.len() <= MIN_FRAME_SIZE);
+        }
+
+        // fragments can arrive in any order
+        fragments.reverse();
+        let mut reassembly = Reassembly::default();
+        let result = reassemble(&mut reassembly, PeerId::random(), fragments);
+        assert_eq!(result, Ok(Some(request)));
+        assert!(reassembly.partials.is_empty());
+        assert_eq!(reassembly.bytes, 0);
+    }
+
+    #[test]
+    fn reject_invalid_index_and_count() {
+        let mut reassembly = Reassembly::default();
+        let peer = PeerId::random();
+        assert!(reassembly.add(peer, 0, 2, 2, vec![0]).is_err());
+        assert!(reassembly.add(peer, 0, 0, 0, vec![0]).is_err());
+        let count = MAX_FRAGMENTS as u32 + 1;
+        assert!(reassembly.add(peer, 0, 0, count, vec![0]).is_err());
+
+        // fragment count must not change
+        assert_eq!(reassembly.add(peer, 1, 0, 2, vec![0]), Ok(None));
+        assert!(reassembly.add(peer, 1, 1, 3, vec![0]).is_err());
+        assert!(reassembly.partials.is_empty());
+        assert_eq!(reassembly.bytes, 0);
+    }
+
+    #[test]
+    fn limit_incomplete_messages() {
+        let mut reassembly = Reassembly::default();
+        let peer = PeerId::random();
+        for id in 0..MAX_PEER_PARTIALS as u32 {
+            assert_eq!(reassembly.add(peer, id, 0, 2, vec![0]), Ok(None));
+        }
+        let id = MAX_PEER_PARTIALS as u32;
+        assert!(reassembly.add(peer, id, 0, 2, vec![0]).is_err());
+        assert_eq!(
+            reassembly.add(PeerId::random(), id, 0, 2, vec![0]),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn limit_buffered_bytes() {
+        let mut reassembly = Reassembly::default();
+        let peer = PeerId::random();
+        let size = MAX_PEER_BYTES / 2;
+        assert_eq!(reassembly.add(peer, 0, 0, 3, vec![0; size]), Ok(None));
+        assert!(reassembly.add(peer, 0, 1, 3, vec![0; size]).is_err());
+        assert!(reassembly.partials.is_empty());
+        assert_eq!(reassembly.bytes, 0);
+    }
+}
@@ -0,0 +1,125 @@
+use libp2p::core::transport::PortUse;
+use libp2p::core::Endpoint;
+use libp2p::swarm::{
+    dummy, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent,
+    THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+
+/// Connection gate with allow and deny lists of peers:
+/// connections with denied peers are always refused, if the allow list is
+/// not empty only connections with allowed peers are accepted
+#[derive(Default)]
+pub struct Gate {
+    allowed: HashSet<PeerId>,
+    blocked: HashSet<PeerId>,
+}
+
+impl Gate {
+    /// check if peer is allowed
+    pub fn is_allowed(&self, peer: &PeerId) -> bool {
+        if self.blocked.contains(peer) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.contains(peer)
+    }
+
+    /// add peer to deny list and remove it from allow list
+    pub fn block(&mut self, peer: PeerId) {
+        self.allowed.remove(&peer);
+        self.blocked.insert(peer);
+    }
+
+    /// remove peer from deny list
+    pub fn unblock(&mut self, peer: &PeerId) {
+        self.blocked.remove(peer);
+    }
+
+    /// add peer to allow list and remove it from deny list
+    pub fn allow(&mut self, peer: PeerId) {
+        self.blocked.remove(&peer);
+        self.allowed.insert(peer);
+    }
+
+    /// remove peer from allow list
+    pub fn disallow(&mut self, peer: &PeerId) {
+        self.allowed.remove(peer);
+    }
+
+    /// get peers in allow list
+    pub fn allowed(&self) -> impl Iterator<Item = &PeerId> {
+        self.allowed.iter()
+    }
+
+    /// get peers in deny list
+    pub fn blocked(&self) -> impl Iterator<Item = &PeerId> {
+        self.blocked.iter()
+    }
+
+    /// refuse connection if peer is not allowed
+    fn enforce(&self, peer: &PeerId) -> Result<(), ConnectionDenied> {
+        if !self.is_allowed(peer) {
+            return Err(ConnectionDenied::new("peer is not allowed"));
+        }
+        Ok(())
+    }
+}
+
+impl NetworkBehaviour for Gate {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.enforce(&peer)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: Option<PeerId>,
+        _: &[Multiaddr],
+        _: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        if let Some(peer) = peer {
+            self.enforce(&peer)?;
+        }
+        Ok(vec![])
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+        _: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.enforce(&peer)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, _: FromSwarm) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
@@ -1,5 +1,11 @@
 use minicbor::{Decode, Encode};
 
+/// current version of announce messages: the upper 4 bits are the major
+/// version, the lower 4 bits the minor version. Minor versions only add
+/// optional fields, so all versions with the same major version are
+/// compatible
+pub const ANNOUNCE_VERSION: u8 = 0x02;
+
 /// announce message that is sent over gossipsub
 #[derive(Debug, Encode, Decode)]
 pub struct HiAnnounce {
@@ -9,17 +15,34 @@ pub struct HiAnnounce {
     pub name: String,
     #[n(2)]
     pub services_tag: u32,
+    /// hi peers directly connected to the announcing peer, used for routing
+    /// messages through other peers, missing in older announcements
+    #[n(3)]
+    pub neighbors: Option<Vec<String>>,
+    /// maximum size of request frames accepted by the announcing peer,
+    /// missing in older announcements
+    #[n(4)]
+    pub max_frame_size: Option<u32>,
 }
 
 impl HiAnnounce {
     pub fn new() -> Self {
         HiAnnounce {
-            version: 0,
+            version: ANNOUNCE_VERSION,
             name: String::new(),
             services_tag: 0,
+            neighbors: None,
+            max_frame_size: None,
         }
     }
 
+    /// check if announce message version is supported: older and newer
+    /// minor versions are accepted so mixed-version networks keep working
+    /// during upgrades, other major versions are not
+    pub fn is_supported(&self) -> bool {
+        self.version >> 4 == ANNOUNCE_VERSION >> 4
+    }
+
     pub fn encode(&self) -> Option<Vec<u8>> {
         let mut buffer = Vec::new();
         match minicbor::encode(self, &mut buffer) {
@@ -41,3 +64,38 @@ impl HiAnnounce {
         }
     }
 }
+
+/// broadcast message that is sent over the gossipsub topic of a service
+#[derive(Debug, Encode, Decode)]
+pub struct HiBroadcast {
+    #[n(0)]
+    pub to_client: u16,
+    #[n(1)]
+    pub from_client: u16,
+    #[n(2)]
+    #[cbor(with = "minicbor::bytes")]
+    pub content: Vec<u8>,
+}
+
+impl HiBroadcast {
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let mut buffer = Vec::new();
+        match minicbor::encode(self, &mut buffer) {
+            Ok(()) => Some(buffer),
+            Err(e) => {
+                error!("HiBroadcast encoding error: {:?}", e);
+                None
+            }
+        }
+    }
+
+    pub fn decode(buffer: &[u8]) -> Option<Self> {
+        match minicbor::decode(buffer) {
+            Ok(msg) => Some(msg),
+            Err(e) => {
+                error!("HiBroadcast decoding error: {:?}", e);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::config::Config;
+use libp2p::identity::Keypair;
+use libp2p::pnet::PreSharedKey;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::{self, AsyncWriteExt};
+
+/// file in the config directory that stores the node's keypair
+const KEY_FILE: &str = "hi.key";
+
+/// file in the config directory that stores the pre-shared key of a
+/// private network in the go-libp2p swarm key format
+const SWARM_KEY_FILE: &str = "swarm.key";
+
+/// get path of the key file
+fn key_file(config: &Config) -> PathBuf {
+    let mut file = config.dir.clone().unwrap();
+    file.push(KEY_FILE);
+    file
+}
+
+/// write keypair to new key file that is only accessible by the owner
+async fn write_keypair(file: &Path, keypair: &Keypair) -> io::Result<()> {
+    let bytes = keypair.to_protobuf_encoding().map_err(io::Error::other)?;
+    if file.exists() {
+        // remove old key file
+        fs::remove_file(file).await?;
+    }
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(file)
+        .await?;
+    f.write_all(&bytes).await?;
+    f.sync_all().await?;
+    Ok(())
+}
+
+/// load the node's keypair from the key file; generate a new keypair and
+/// store it in the key file if there is no key file or `regenerate` is set
+pub async fn load(config: &Config, regenerate: bool) -> io::Result<Keypair> {
+    let file = key_file(config);
+
+    // read existing keypair
+    if !regenerate && file.exists() {
+        let bytes = fs::read(&file).await?;
+        return Keypair::from_protobuf_encoding(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+
+    // create and store new keypair
+    let keypair = Keypair::generate_ed25519();
+    write_keypair(&file, &keypair).await?;
+    debug!("created new node identity in {:?}", file);
+    Ok(keypair)
+}
+
+/// load the pre-shared key of the private network from the swarm key file
+pub async fn load_swarm_key(config: &Config) -> io::Result<PreSharedKey> {
+    let mut file = config.dir.clone().unwrap();
+    file.push(SWARM_KEY_FILE);
+    let key = fs::read_to_string(&file).await?;
+    key.parse::<PreSharedKey>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,246 @@
+use crate::config::Config;
+use crate::message::{DeliveryError, OutboxInfo};
+use minicbor::{Decode, Encode};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::io::{self, AsyncWriteExt};
+
+/// file in the config directory that stores the outbox
+const OUTBOX_FILE: &str = "outbox";
+
+/// default time to live of messages in the outbox in seconds
+pub const DEFAULT_OUTBOX_TTL: u64 = 60 * 60;
+
+/// maximum number of messages in the outbox
+const MAX_MESSAGES: usize = 1024;
+
+/// get current time in seconds since the unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("timestamp error")
+        .as_secs()
+}
+
+/// check if a message that failed with `error` can be kept in the outbox:
+/// only messages that never reached the peer because it was unreachable or
+/// unknown, timed out messages may have been delivered already
+pub fn can_keep(error: &DeliveryError) -> bool {
+    matches!(
+        error,
+        DeliveryError::DialFailure | DeliveryError::UnknownPeer
+    )
+}
+
+/// Message waiting in the outbox
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct OutboxMessage {
+    #[n(0)]
+    pub id: u32,
+    #[n(1)]
+    pub to_peer: String,
+    #[n(2)]
+    pub to_client: u16,
+    #[n(3)]
+    pub from_client: u16,
+    #[n(4)]
+    pub service: u16,
+    #[n(5)]
+    #[cbor(with = "minicbor::bytes")]
+    pub content: Vec<u8>,
+    /// message id chosen by the sending client
+    #[n(6)]
+    pub message_id: u32,
+    #[n(7)]
+    pub created: u64,
+    #[n(8)]
+    pub expires: u64,
+    /// message was loaded from the outbox file, its source client did not
+    /// survive the daemon restart
+    #[n(9)]
+    pub restored: Option<bool>,
+}
+
+impl OutboxMessage {
+    /// create message for `to_peer` that is not queued yet
+    pub fn new(
+        to_peer: String,
+        to_client: u16,
+        from_client: u16,
+        service: u16,
+        content: Vec<u8>,
+        message_id: u32,
+    ) -> Self {
+        OutboxMessage {
+            id: 0,
+            to_peer,
+            to_client,
+            from_client,
+            service,
+            content,
+            message_id,
+            created: 0,
+            expires: 0,
+            restored: None,
+        }
+    }
+
+    /// check if the result of the message cannot be reported because its
+    /// source client belongs to a previous daemon run
+    pub fn is_restored(&self) -> bool {
+        self.restored.unwrap_or(false)
+    }
+
+    /// get information about this message for clients
+    fn info(&self) -> OutboxInfo {
+        OutboxInfo {
+            id: self.id,
+            to_peer: self.to_peer.clone(),
+            to_client: self.to_client,
+            from_client: self.from_client,
+            service: self.service,
+            size: self.content.len() as u64,
+            created: self.created,
+            expires: self.expires,
+        }
+    }
+}
+
+/// Outbox that keeps messages for unreachable peers in a file in the
+/// config directory until the peers are back or the messages expire
+pub struct Outbox {
+    file: PathBuf,
+    /// time to live of messages in seconds, outbox is disabled if 0
+    ttl: u64,
+    next_id: u32,
+    messages: Vec<OutboxMessage>,
+}
+
+impl Outbox {
+    /// load outbox from the outbox file with time to live `ttl` of new messages
+    pub async fn load(config: &Config, ttl: u64) -> io::Result<Self> {
+        let mut file = config.dir.clone().unwrap();
+        file.push(OUTBOX_FILE);
+
+        // read existing messages, source clients do not survive a daemon
+        // restart, so results of these messages are not reported
+        let mut messages: Vec<OutboxMessage> = Vec::new();
+        if file.exists() {
+            let bytes = fs::read(&file).await?;
+            messages = minicbor::decode(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            for message in messages.iter_mut() {
+                message.restored = Some(true);
+            }
+            debug!("loaded {} messages from outbox", messages.len());
+        }
+        let next_id = messages.iter().map(|m| m.id.wrapping_add(1)).max();
+        Ok(Outbox {
+            file,
+            ttl,
+            next_id: next_id.unwrap_or(0),
+            messages,
+        })
+    }
+
+    /// write messages to the outbox file that is only accessible by the owner
+    async fn write(&self) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        minicbor::encode(&self.messages, &mut bytes).map_err(io::Error::other)?;
+
+        // replace outbox file with temporary file
+        let mut tmp = self.file.clone();
+        tmp.set_extension("tmp");
+        let mut f = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)
+            .await?;
+        f.write_all(&bytes).await?;
+        f.sync_all().await?;
+        fs::rename(&tmp, &self.file).await
+    }
+
+    /// save messages in the outbox file
+    async fn save(&self) {
+        if let Err(e) = self.write().await {
+            error!("error writing outbox file {:?}: {}", self.file, e);
+        }
+    }
+
+    /// check if the outbox is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.ttl > 0
+    }
+
+    /// add `message` to the outbox, messages that were queued before keep
+    /// their expiry time
+    pub async fn add(&mut self, mut message: OutboxMessage) -> Result<(), String> {
+        if self.messages.len() >= MAX_MESSAGES {
+            return Err(String::from("outbox full"));
+        }
+        if message.created == 0 {
+            message.created = now();
+            message.expires = message.created + self.ttl;
+        }
+        message.id = self.next_id;
+        self.messages.push(message);
+        self.next_id = self.next_id.wrapping_add(1);
+        self.save().await;
+        Ok(())
+    }
+
+    /// remove and return messages that match `filter`
+    async fn remove(&mut self, filter: impl Fn(&OutboxMessage) -> bool) -> Vec<OutboxMessage> {
+        let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.messages)
+            .into_iter()
+            .partition(filter);
+        self.messages = kept;
+        if !removed.is_empty() {
+            self.save().await;
+        }
+        removed
+    }
+
+    /// remove and return messages for `peer`
+    pub async fn take(&mut self, peer: &str) -> Vec<OutboxMessage> {
+        self.remove(|m| m.to_peer == peer).await
+    }
+
+    /// remove and return expired messages
+    pub async fn expire(&mut self) -> Vec<OutboxMessage> {
+        let now = now();
+        self.remove(|m| m.expires <= now).await
+    }
+
+    /// remove and return messages for `peer`, all messages if empty
+    pub async fn purge(&mut self, peer: &str) -> Vec<OutboxMessage> {
+        self.remove(|m| peer.is_empty() || m.to_peer == peer).await
+    }
+
+    /// get information about all messages in the outbox
+    pub fn list(&self) -> Vec<OutboxInfo> {
+        self.messages.iter().map(OutboxMessage::info).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_unreachable_messages() {
+        assert!(can_keep(&DeliveryError::DialFailure));
+        assert!(can_keep(&DeliveryError::UnknownPeer));
+    }
+
+    #[test]
+    fn do_not_keep_timed_out_messages() {
+        assert!(!can_keep(&DeliveryError::Timeout));
+        assert!(!can_keep(&DeliveryError::RemoteError(String::new())));
+        assert!(!can_keep(&DeliveryError::Other(String::new())));
+    }
+}
@@ -0,0 +1,168 @@
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::PeerId;
+use minicbor::{Decode, Encode};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// maximum number of hops of a relayed message
+pub const MAX_HOPS: u8 = 8;
+
+/// Content of a relayed message
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum RelayContent {
+    /// Message: destination client, source client, service, content,
+    /// relay id acknowledged by the destination
+    #[n(0)]
+    Message(
+        #[n(0)] u16,
+        #[n(1)] u16,
+        #[n(2)] u16,
+        #[n(3)]
+        #[cbor(with = "minicbor::bytes")]
+        Vec<u8>,
+        #[n(4)] u32,
+    ),
+    /// Trace request: trace id
+    #[n(1)]
+    TraceRequest(#[n(0)] u32),
+    /// Trace reply: trace id, path from the source to the destination of the
+    /// trace request
+    #[n(2)]
+    TraceReply(#[n(0)] u32, #[n(1)] Vec<String>),
+    /// Acknowledgement of a delivered message: relay id
+    #[n(3)]
+    Ack(#[n(0)] u32),
+}
+
+/// Message that is relayed hop-by-hop through other peers
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct RelayMessage {
+    /// destination peer
+    #[n(0)]
+    pub to_peer: String,
+    /// source peer
+    #[n(1)]
+    pub from_peer: String,
+    /// remaining number of hops
+    #[n(2)]
+    pub hops: u8,
+    /// peers the message passed so far, starting with the source peer
+    #[n(3)]
+    pub path: Vec<String>,
+    #[n(4)]
+    pub content: RelayContent,
+    /// encoded public key of the source peer
+    #[n(5)]
+    #[cbor(with = "minicbor::bytes")]
+    pub public_key: Vec<u8>,
+    /// signature of the source peer over destination, source and content
+    #[n(6)]
+    #[cbor(with = "minicbor::bytes")]
+    pub signature: Vec<u8>,
+}
+
+impl RelayMessage {
+    /// create relay message from `from_peer` to `to_peer` with `content`
+    pub fn new(from_peer: String, to_peer: String, content: RelayContent) -> Self {
+        RelayMessage {
+            to_peer,
+            path: vec![from_peer.clone()],
+            from_peer,
+            hops: MAX_HOPS,
+            content,
+            public_key: Vec::new(),
+            signature: Vec::new(),
+        }
+    }
+
+    /// encode the signed part of the message
+    fn signed_data(&self) -> Result<Vec<u8>, String> {
+        let mut buffer = Vec::new();
+        let data = (&self.to_peer, &self.from_peer, &self.content);
+        match minicbor::encode(data, &mut buffer) {
+            Ok(()) => Ok(buffer),
+            Err(e) => Err(format!("error encoding relay message: {}", e)),
+        }
+    }
+
+    /// sign message with the identity `keypair` of the source peer
+    pub fn sign(&mut self, keypair: &Keypair) -> Result<(), String> {
+        let data = self.signed_data()?;
+        self.signature = keypair.sign(&data).map_err(|e| e.to_string())?;
+        self.public_key = keypair.public().encode_protobuf();
+        Ok(())
+    }
+
+    /// verify that the message was signed by its source peer
+    pub fn verify(&self) -> Result<(), String> {
+        let public_key = PublicKey::try_decode_protobuf(&self.public_key)
+            .map_err(|_| String::from("invalid public key"))?;
+        if PeerId::from_public_key(&public_key).to_base58() != self.from_peer {
+            return Err(String::from("public key does not match source peer"));
+        }
+        if !public_key.verify(&self.signed_data()?, &self.signature) {
+            return Err(String::from("invalid signature"));
+        }
+        Ok(())
+    }
+}
+
+/// Routing table built from the neighbors announced by other peers
+#[derive(Default)]
+pub struct Routes {
+    neighbors: HashMap<String, HashSet<String>>,
+}
+
+impl Routes {
+    /// set announced neighbors of `peer`
+    pub fn update(&mut self, peer: String, neighbors: Vec<String>) {
+        self.neighbors.insert(peer, neighbors.into_iter().collect());
+    }
+
+    /// remove `peer` from routing table
+    pub fn remove(&mut self, peer: &str) {
+        self.neighbors.remove(peer);
+    }
+
+    /// find shortest path from this node `own_peer` to `to_peer` starting at
+    /// one of the directly connected peers in `direct`, the path does not
+    /// include this node
+    pub fn find(
+        &self,
+        own_peer: &str,
+        direct: &HashSet<String>,
+        to_peer: &str,
+    ) -> Option<Vec<String>> {
+        let mut visited: HashSet<&str> = HashSet::from([own_peer]);
+        let mut queue = VecDeque::new();
+        for peer in direct {
+            if peer == to_peer {
+                return Some(vec![peer.clone()]);
+            }
+            visited.insert(peer);
+            queue.push_back(vec![peer.as_str()]);
+        }
+
+        // breadth-first search limited to the maximum number of hops
+        while let Some(path) = queue.pop_front() {
+            if path.len() >= MAX_HOPS as usize {
+                continue;
+            }
+            let neighbors = match self.neighbors.get(*path.last().unwrap()) {
+                Some(neighbors) => neighbors,
+                None => continue,
+            };
+            for neighbor in neighbors {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let mut next = path.clone();
+                next.push(neighbor.as_str());
+                if neighbor == to_peer {
+                    return Some(next.into_iter().map(String::from).collect());
+                }
+                queue.push_back(next);
+            }
+        }
+        None
+    }
+}
@@ -1,22 +1,166 @@
+use crate::daemon::relay::RelayMessage;
 use async_trait::async_trait;
 use futures::prelude::*;
 use libp2p::request_response;
 use minicbor::{Decode, Encode};
 use tokio::io;
 
+/// Supported request protocol versions, preferred version first:
+/// 0.1.0 adds ping requests to the legacy version 0.0.1,
+/// 0.2.0 adds length-prefixed framing and message fragments,
+/// 0.3.0 adds messages relayed through other peers
+pub const REQUEST_PROTOCOL_VERSIONS: [&str; 4] = ["0.3.0", "0.2.0", "0.1.0", "0.0.1"];
+
+/// first request protocol version with ping requests
+const PING_VERSION: &str = "0.1.0";
+
+/// first request protocol version with length-prefixed framing
+const FRAMED_VERSION: &str = "0.2.0";
+
+/// first request protocol version with relayed messages
+pub const RELAY_VERSION: &str = "0.3.0";
+
+/// default maximum size of a request or response frame
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+
 /// Request-response protocol for the request-response behaviour
 #[derive(Debug, Clone)]
-pub struct HiRequestProtocol();
+pub struct HiRequestProtocol {
+    name: String,
+    version: &'static str,
+}
+
+impl HiRequestProtocol {
+    /// create request protocol with `version` of the network `network`,
+    /// default network if empty
+    pub fn new(network: &str, version: &'static str) -> Self {
+        let name = if network.is_empty() {
+            format!("/hi/request/{}", version)
+        } else {
+            format!("/hi/{}/request/{}", network, version)
+        };
+        HiRequestProtocol { name, version }
+    }
+
+    /// create all supported request protocol versions of the network `network`
+    pub fn all(network: &str) -> Vec<Self> {
+        REQUEST_PROTOCOL_VERSIONS
+            .iter()
+            .map(|version| HiRequestProtocol::new(network, version))
+            .collect()
+    }
+
+    /// get version of the request protocol
+    pub fn version(&self) -> &'static str {
+        self.version
+    }
+
+    /// check if this protocol version is `version` or newer
+    fn is_at_least(&self, version: &str) -> bool {
+        let position = |v| REQUEST_PROTOCOL_VERSIONS.iter().position(|x| *x == v);
+        position(self.version) <= position(version)
+    }
+
+    /// check if messages are length-prefixed in this protocol version
+    fn is_framed(&self) -> bool {
+        self.is_at_least(FRAMED_VERSION)
+    }
+
+    /// check if `request` is supported by this protocol version
+    fn supports(&self, request: &HiRequest) -> bool {
+        match request {
+            HiRequest::Message(..) => true,
+            HiRequest::Ping => self.is_at_least(PING_VERSION),
+            HiRequest::Fragment(..) => self.is_framed(),
+            HiRequest::Relay(..) => self.is_at_least(RELAY_VERSION),
+        }
+    }
+}
 
 impl AsRef<str> for HiRequestProtocol {
     fn as_ref(&self) -> &str {
-        "/hi/request/0.0.1"
+        &self.name
     }
 }
 
+/// create error for requests not supported by protocol version
+fn unsupported_error(protocol: &HiRequestProtocol) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("request not supported by protocol {}", protocol.as_ref()),
+    )
+}
+
 /// Codec for the request-response behaviour
-#[derive(Clone, Default)]
-pub struct HiCodec();
+#[derive(Clone)]
+pub struct HiCodec {
+    /// maximum size of a request or response frame
+    max_frame_size: usize,
+}
+
+impl HiCodec {
+    /// create codec with maximum frame size `max_frame_size`
+    pub fn new(max_frame_size: usize) -> Self {
+        HiCodec { max_frame_size }
+    }
+
+    /// read message with `protocol` from `io`
+    async fn read<T, M>(&self, protocol: &HiRequestProtocol, io: &mut T) -> io::Result<M>
+    where
+        T: AsyncRead + Unpin + Send,
+        M: for<'b> Decode<'b, ()>,
+    {
+        let mut vec = Vec::new();
+        if protocol.is_framed() {
+            // read length prefix and frame
+            let mut len = [0; 4];
+            io.read_exact(&mut len).await?;
+            let len = u32::from_be_bytes(len) as usize;
+            if len > self.max_frame_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("frame size {} exceeds maximum {}", len, self.max_frame_size),
+                ));
+            }
+            vec.resize(len, 0);
+            io.read_exact(&mut vec).await?;
+        } else {
+            // read until the remote closes the stream
+            io.take(self.max_frame_size as u64)
+                .read_to_end(&mut vec)
+                .await?;
+        }
+        minicbor::decode(&vec).map_err(io::Error::other)
+    }
+
+    /// write message with `protocol` to `io`
+    async fn write<T, M>(&self, protocol: &HiRequestProtocol, io: &mut T, msg: M) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+        M: Encode<()>,
+    {
+        let mut buffer = Vec::new();
+        if let Err(e) = minicbor::encode(msg, &mut buffer) {
+            error!("error encoding message: {}", e);
+            return Err(io::Error::other(e));
+        }
+        if buffer.len() > self.max_frame_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "message size {} exceeds maximum {}",
+                    buffer.len(),
+                    self.max_frame_size
+                ),
+            ));
+        }
+        if protocol.is_framed() {
+            let len = buffer.len() as u32;
+            io.write_all(&len.to_be_bytes()).await?;
+        }
+        io.write_all(buffer.as_ref()).await
+    }
+}
 
 #[async_trait]
 impl request_response::Codec for HiCodec {
@@ -26,68 +170,63 @@ impl request_response::Codec for HiCodec {
 
     async fn read_request<T>(
         &mut self,
-        _: &HiRequestProtocol,
+        protocol: &HiRequestProtocol,
         io: &mut T,
     ) -> io::Result<Self::Request>
     where
         T: AsyncRead + Unpin + Send,
     {
-        let mut vec = Vec::new();
-        io.take(1024).read_to_end(&mut vec).await?;
-        minicbor::decode(&vec).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        let request = self.read(protocol, io).await?;
+        if !protocol.supports(&request) {
+            return Err(unsupported_error(protocol));
+        }
+        Ok(request)
     }
 
     async fn read_response<T>(
         &mut self,
-        _: &HiRequestProtocol,
+        protocol: &HiRequestProtocol,
         io: &mut T,
     ) -> io::Result<Self::Response>
     where
         T: AsyncRead + Unpin + Send,
     {
-        let mut vec = Vec::new();
-        io.take(1024).read_to_end(&mut vec).await?;
-        minicbor::decode(&vec).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        self.read(protocol, io).await
     }
 
     async fn write_request<T>(
         &mut self,
-        _: &HiRequestProtocol,
+        protocol: &HiRequestProtocol,
         io: &mut T,
         request: HiRequest,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
-        let mut buffer = Vec::new();
-        if let Err(e) = minicbor::encode(request, &mut buffer) {
-            error!("error encoding request message: {}", e);
-            return Err(io::Error::new(io::ErrorKind::Other, e));
+        if !protocol.supports(&request) {
+            return Err(unsupported_error(protocol));
         }
-        io.write_all(buffer.as_ref()).await
+        self.write(protocol, io, request).await
     }
 
     async fn write_response<T>(
         &mut self,
-        _: &HiRequestProtocol,
+        protocol: &HiRequestProtocol,
         io: &mut T,
         response: HiResponse,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
-        let mut buffer = Vec::new();
-        if let Err(e) = minicbor::encode(response, &mut buffer) {
-            error!("error encoding response message: {}", e);
-            return Err(io::Error::new(io::ErrorKind::Other, e));
-        }
-        io.write_all(buffer.as_ref()).await
+        self.write(protocol, io, response).await
     }
 }
 
 /// Request message
 #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
 pub enum HiRequest {
+    /// Message: destination client, source client, service, content,
+    /// message id for dropping retransmitted duplicates
     #[n(0)]
     Message(
         #[n(0)] u16,
@@ -96,7 +235,24 @@ pub enum HiRequest {
         #[n(3)]
         #[cbor(with = "minicbor::bytes")]
         Vec<u8>,
+        #[n(4)] Option<u32>,
+    ),
+    /// Ping of older versions, answered but no longer sent
+    #[n(1)]
+    Ping,
+    /// Fragment of an encoded request: id, index, number of fragments, data
+    #[n(2)]
+    Fragment(
+        #[n(0)] u32,
+        #[n(1)] u32,
+        #[n(2)] u32,
+        #[n(3)]
+        #[cbor(with = "minicbor::bytes")]
+        Vec<u8>,
     ),
+    /// Message relayed through other peers
+    #[n(3)]
+    Relay(#[n(0)] RelayMessage),
 }
 
 /// Response message
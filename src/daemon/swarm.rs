@@ -1,47 +1,349 @@
+use crate::daemon::addresses::AddressBook;
 use crate::daemon::behaviour::{HiBehaviour, HiBehaviourEvent};
-use crate::daemon::gossip::HiAnnounce;
-use crate::daemon::request::{HiRequest, HiRequestProtocol, HiResponse};
+use crate::daemon::fragment::{self, Reassembly};
+use crate::daemon::gate::Gate;
+use crate::daemon::gossip::{HiAnnounce, HiBroadcast};
+use crate::daemon::relay::{RelayContent, RelayMessage};
+use crate::daemon::request::{self, HiCodec, HiRequest, HiRequestProtocol, HiResponse};
+use crate::message::{DeliveryError, Message};
 use futures::{channel::mpsc, prelude::*, sink::SinkExt};
+use libp2p::core::transport::ListenerId;
+use libp2p::core::upgrade::Version;
+use libp2p::multiaddr::Protocol;
+use libp2p::pnet::{PnetConfig, PreSharedKey};
 use libp2p::swarm::{Swarm, SwarmEvent};
-use libp2p::{gossipsub, mdns, request_response, Multiaddr, PeerId, SwarmBuilder};
+use libp2p::Transport as _;
+use libp2p::{
+    gossipsub, identify, identity, kad, mdns, noise, ping, request_response, tcp, yamux, Multiaddr,
+    PeerId, StreamProtocol, SwarmBuilder,
+};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::iter;
 use std::str::FromStr;
 use tokio::time::{self, Duration, Instant};
 
-/// gossipsub topic
-// TODO: change /hello/world to other topic?
+/// gossipsub topic of the default network
 const TOPIC: &str = "/hello/world";
 
+/// prefix of the gossipsub topics of services in the default network
+const SERVICE_TOPIC_PREFIX: &str = "/hi/service";
+
+/// prefix of the gossipsub topics of clients in the default network, does
+/// not overlap with the topics of named networks
+const CLIENT_TOPIC_PREFIX: &str = "/hi-topic";
+
+/// maximum length of client topic names
+pub const MAX_TOPIC_LENGTH: usize = 256;
+
+/// time to remember received broadcast messages for deduplication
+const SEEN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// time to remember ids of received messages for dropping retransmitted
+/// duplicates, longer than the send timeout of most peers
+const RECEIVED_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// maximum number of remembered ids of received messages
+const MAX_RECEIVED: usize = 65536;
+
+/// time to wait for the next round-trip time of a pinged peer, longer than
+/// the interval and timeout of the ping protocol
+const PING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// identify protocol version
+const IDENTIFY_PROTOCOL_VERSION: &str = "/hi/1.0.0";
+
+/// kademlia protocol of the default network
+const KAD_PROTOCOL: StreamProtocol = StreamProtocol::new("/hi/kad/1.0.0");
+
+/// default timeout of outbound messages
+pub const DEFAULT_SEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// default number of retransmissions of failed outbound requests
+pub const DEFAULT_SEND_RETRIES: u32 = 5;
+
+/// initial delay before retransmitting a failed request, doubled on each retry
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// maximum delay before retransmitting a failed request
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(16);
+
+/// default TCP listen addresses: all IPs and random ports
+const TCP_LISTEN_ADDRESSES: [&str; 2] = ["/ip6/::/tcp/0", "/ip4/0.0.0.0/tcp/0"];
+
+/// default QUIC listen addresses: all IPs and random ports
+const QUIC_LISTEN_ADDRESSES: [&str; 2] = ["/ip6/::/udp/0/quic-v1", "/ip4/0.0.0.0/udp/0/quic-v1"];
+
 type Sender<T> = mpsc::UnboundedSender<T>;
 type Receiver<T> = mpsc::UnboundedReceiver<T>;
 
+/// Hi swarm transports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// TCP with TLS or noise and yamux
+    Tcp,
+    /// QUIC
+    Quic,
+    /// TCP and QUIC
+    All,
+}
+
+impl Transport {
+    /// get default listen addresses of the transport
+    fn default_listen_addresses(&self) -> Vec<String> {
+        let addresses: Vec<&str> = match self {
+            Transport::Tcp => TCP_LISTEN_ADDRESSES.to_vec(),
+            Transport::Quic => QUIC_LISTEN_ADDRESSES.to_vec(),
+            Transport::All => [TCP_LISTEN_ADDRESSES, QUIC_LISTEN_ADDRESSES].concat(),
+        };
+        addresses.iter().map(|a| a.to_string()).collect()
+    }
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tcp" => Ok(Transport::Tcp),
+            "quic" => Ok(Transport::Quic),
+            "all" => Ok(Transport::All),
+            _ => Err(format!("invalid transport: {}", s)),
+        }
+    }
+}
+
+/// Hi network: gossipsub topic and protocols derived from the network name,
+/// so daemons in different networks ignore each other. Note: the mdns
+/// service name is fixed in libp2p, so peers in other networks are still
+/// discovered on the LAN, but they do not share any protocols with us
+#[derive(Debug, Clone)]
+struct Network {
+    topic: gossipsub::IdentTopic,
+    service_topic_prefix: String,
+    client_topic_prefix: String,
+    request_protocols: Vec<HiRequestProtocol>,
+    kad_protocol: StreamProtocol,
+}
+
+impl Network {
+    /// create network with name `name`, default network if empty
+    fn new(name: &str) -> Result<Self, Box<dyn Error>> {
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        {
+            return Err(format!("invalid network name: {}", name).into());
+        }
+        if name.is_empty() {
+            return Ok(Network {
+                topic: gossipsub::IdentTopic::new(TOPIC),
+                service_topic_prefix: String::from(SERVICE_TOPIC_PREFIX),
+                client_topic_prefix: String::from(CLIENT_TOPIC_PREFIX),
+                request_protocols: HiRequestProtocol::all(name),
+                kad_protocol: KAD_PROTOCOL,
+            });
+        }
+        Ok(Network {
+            topic: gossipsub::IdentTopic::new(format!("/hi/{}/announce", name)),
+            service_topic_prefix: format!("/hi/{}/service", name),
+            client_topic_prefix: format!("/hi-topic-net/{}", name),
+            request_protocols: HiRequestProtocol::all(name),
+            kad_protocol: StreamProtocol::try_from_owned(format!("/hi/{}/kad/1.0.0", name))?,
+        })
+    }
+
+    /// get gossipsub topic of broadcast messages of `service`
+    fn service_topic(&self, service: u16) -> gossipsub::IdentTopic {
+        gossipsub::IdentTopic::new(format!("{}/{}", self.service_topic_prefix, service))
+    }
+
+    /// get gossipsub topic of the client topic `name`
+    fn client_topic(&self, name: &str) -> gossipsub::IdentTopic {
+        gossipsub::IdentTopic::new(format!("{}/{}", self.client_topic_prefix, name))
+    }
+}
+
+/// Hi swarm options
+pub struct Options {
+    /// node identity
+    pub keypair: identity::Keypair,
+    /// listen addresses, default addresses of the transport are used if empty
+    pub listen: Vec<String>,
+    /// transports for listening and dialing
+    pub transport: Transport,
+    /// kademlia bootstrap peers: multiaddresses including the peer id
+    pub bootstrap: Vec<String>,
+    /// pre-shared key of the private network, only supported with TCP
+    pub psk: Option<PreSharedKey>,
+    /// allowed peers, all peers are allowed if empty
+    pub allow: Vec<String>,
+    /// blocked peers
+    pub block: Vec<String>,
+    /// network name, default network if empty
+    pub network: String,
+    /// maximum size of request and response frames, larger messages are
+    /// split into fragments
+    pub max_frame_size: usize,
+    /// timeout of outbound messages including retransmissions
+    pub send_timeout: Duration,
+    /// maximum number of retransmissions of failed outbound requests
+    pub send_retries: u32,
+}
+
 /// Hi swarm events
 #[derive(Debug)]
 pub enum Event {
     /// Connect to peer address: multiaddress
     ConnectAddress(String),
+    /// Listen on address: multiaddress
+    ListenAddress(String),
+    /// Stop listening on address: multiaddress
+    UnlistenAddress(String),
     /// Set node's name: name
     SetName(String),
     /// Set tag of the services supported by this node
     SetServicesTag(u32),
-    /// Send message: destination peer, destination client, source client, service, content
-    SendMessage(String, u16, u16, u16, Vec<u8>),
+    /// Send message: destination peer, destination client, source client, service, content,
+    /// message id
+    SendMessage(String, u16, u16, u16, Vec<u8>, u32),
+    /// Send relay message: next hop, relay message, source client, message id
+    SendRelay(String, RelayMessage, u16, u32),
+    /// Broadcast message to all peers: destination client, source client,
+    /// service, content, message id
+    Broadcast(u16, u16, u16, Vec<u8>, u32),
+    /// Subscribe to broadcast messages of services, unsubscribe from all
+    /// other services: services
+    SubscribeServices(HashSet<u16>),
+    /// Subscribe to client topics, unsubscribe from all other client
+    /// topics: topic names
+    SubscribeTopics(HashSet<String>),
+    /// Publish content on client topic: source client, topic name, content
+    Publish(u16, String, Vec<u8>),
+    /// Ping peer: destination peer, source client, request id
+    Ping(String, u16, u32),
+    /// Block peer: id
+    BlockPeer(String),
+    /// Unblock peer: id
+    UnblockPeer(String),
+    /// Allow peer: id
+    AllowPeer(String),
+    /// Disallow peer: id
+    DisallowPeer(String),
+    /// Get peers in allow or deny list: source client, request id, deny list
+    GetGatePeers(u16, u32, bool),
 
-    /// Peer announcement event: id, name, services tag, file
-    AnnouncePeer(String, String, u32),
+    /// Peer announcement event: id, name, services tag, announce version,
+    /// neighbors
+    AnnouncePeer(String, String, u32, u8, Vec<String>),
     /// Message: sender, sender client, destination client, service, message
     Message(String, u16, u16, u16, Vec<u8>),
+    /// Relay message: previous hop, relay message
+    Relay(String, RelayMessage),
+    /// Message on client topic: topic name, source peer, content
+    TopicMessage(String, String, Vec<u8>),
+    /// New listen address of this node: multiaddress
+    NewListenAddress(String),
+    /// Expired listen address of this node: multiaddress
+    ExpiredListenAddress(String),
+    /// Peer identified: id, agent version, protocol version, listen addresses,
+    /// our address observed by the peer, supported request protocol versions
+    PeerIdentified(String, String, String, Vec<String>, String, Vec<String>),
+    /// Connection to peer opened or closed: id, connected
+    PeerConnection(String, bool),
+    /// Round-trip time to peer measured by ping protocol: id, rtt in microseconds
+    PeerRtt(String, u64),
+    /// Result of a ping request: source client, request id, destination peer,
+    /// rtt in microseconds or error
+    PingResult(u16, u32, String, Result<u64, String>),
+    /// Result of sending a message: source client, message id, destination peer,
+    /// delivered or error
+    MessageResult(u16, u32, String, Result<(), DeliveryError>),
+    /// Result of publishing on a client topic: source client, published or
+    /// error
+    PublishResult(u16, Result<(), String>),
+    /// Peers in allow or deny list: source client, request id, deny list,
+    /// peer ids
+    GatePeers(u16, u32, bool, Vec<String>),
+}
+
+/// Outbound message waiting for the responses to its requests
+struct PendingMessage {
+    /// source client
+    client: u16,
+    /// message id chosen by the source client
+    message_id: u32,
+    /// next hop of the message
+    peer: PeerId,
+    /// destination peer, differs from the next hop for relayed messages
+    destination: String,
+    /// number of requests without response
+    requests: usize,
+    /// time when message delivery fails with a timeout
+    deadline: Instant,
+}
+
+/// Outbound request of a pending message
+struct OutboundRequest {
+    /// pending message
+    key: u64,
+    /// request, kept for retransmissions
+    request: HiRequest,
+    /// number of previous attempts
+    attempt: u32,
 }
 
 /// Hi swarm handler
 struct HiSwarmHandler {
     swarm: Swarm<HiBehaviour>,
+    /// node identity, signs relay messages sent by this node
+    keypair: identity::Keypair,
     receiver: Receiver<Event>,
     sender: Sender<Event>,
+    network: Network,
 
     node_name: String,
     services_tag: u32,
+    listeners: HashMap<Multiaddr, ListenerId>,
+    /// identified peers that can relay messages
+    relay_peers: HashSet<PeerId>,
+    /// services with subscribed broadcast topics
+    service_topics: HashMap<gossipsub::TopicHash, u16>,
+    /// subscribed client topics
+    client_topics: HashMap<gossipsub::TopicHash, String>,
+    /// received broadcast messages for deduplication and the time they expire
+    seen: HashMap<gossipsub::MessageId, Instant>,
+    /// ids of messages received from source peers for dropping
+    /// retransmitted duplicates and the time they expire
+    received: HashMap<(PeerId, u32), Instant>,
+
+    /// requests waiting for a kademlia lookup of the destination peer
+    lookups: HashMap<PeerId, Vec<OutboundRequest>>,
+
+    /// pending outbound messages
+    messages: HashMap<u64, PendingMessage>,
+    /// outbound requests waiting for a response
+    requests: HashMap<request_response::OutboundRequestId, OutboundRequest>,
+    /// failed outbound requests waiting for retransmission at a specific time
+    retries: Vec<(Instant, OutboundRequest)>,
+    /// id of the next pending outbound message
+    next_message: u64,
+    /// timeout of outbound messages
+    send_timeout: Duration,
+    /// maximum number of retransmissions of failed outbound requests
+    send_retries: u32,
+
+    /// ping requests waiting for the next round-trip time of the destination
+    /// peer: source client, request id, deadline
+    pings: HashMap<PeerId, Vec<(u16, u32, Instant)>>,
+
+    /// maximum size of request and response frames
+    max_frame_size: usize,
+    /// maximum frame sizes announced by connected peers
+    peer_frame_sizes: HashMap<PeerId, usize>,
+    /// id of the next fragmented message
+    fragment_id: u32,
+    /// reassembly of fragmented messages from other peers
+    reassembly: Reassembly,
 }
 
 impl HiSwarmHandler {
@@ -58,6 +360,28 @@ impl HiSwarmHandler {
                 }
             }
 
+            // handle listen address event
+            Event::ListenAddress(addr) => {
+                if let Err(e) = self.listen(&addr) {
+                    error!("error listening on address {}: {}", addr, e);
+                }
+            }
+
+            // handle unlisten address event
+            Event::UnlistenAddress(addr) => {
+                let listener = match addr.parse::<Multiaddr>() {
+                    Ok(addr) => self.listeners.remove(&addr),
+                    Err(_) => None,
+                };
+                match listener {
+                    Some(listener) => {
+                        println!("stop listening on address: {}", addr);
+                        self.swarm.remove_listener(listener);
+                    }
+                    None => error!("not listening on address: {}", addr),
+                }
+            }
+
             // handle set name request
             Event::SetName(name) => {
                 self.node_name = name.clone();
@@ -68,27 +392,480 @@ impl HiSwarmHandler {
                 self.services_tag = tag;
             }
 
-            // handle send file message request
-            Event::SendMessage(to_peer, to_client, from_client, service, content) => {
+            // handle send message request
+            Event::SendMessage(to_peer, to_client, from_client, service, content, message_id) => {
+                let msg =
+                    HiRequest::Message(to_client, from_client, service, content, Some(message_id));
+                self.send_message(to_peer.clone(), to_peer, msg, from_client, message_id)
+                    .await;
+            }
+
+            // handle send relay message request
+            Event::SendRelay(next_hop, mut relay, from_client, message_id) => {
+                // sign relay messages sent by this node
+                if relay.from_peer == self.swarm.local_peer_id().to_base58() {
+                    if let Err(e) = relay.sign(&self.keypair) {
+                        let result = Err(DeliveryError::Other(e));
+                        let to_peer = relay.to_peer;
+                        let event = Event::MessageResult(from_client, message_id, to_peer, result);
+                        self.send_daemon_event(event).await;
+                        return;
+                    }
+                }
+                let to_peer = relay.to_peer.clone();
+                let msg = HiRequest::Relay(relay);
+                self.send_message(next_hop, to_peer, msg, from_client, message_id)
+                    .await;
+            }
+
+            // handle broadcast message request
+            Event::Broadcast(to_client, from_client, service, content, message_id) => {
+                let topic = self.network.service_topic(service);
+                let msg = HiBroadcast {
+                    to_client,
+                    from_client,
+                    content,
+                };
+                let result = match msg.encode() {
+                    Some(data) => match self.swarm.behaviour_mut().gossip.publish(topic, data) {
+                        Ok(_) => Ok(()),
+                        // no other peer supports the service
+                        Err(gossipsub::PublishError::NoPeersSubscribedToTopic) => Ok(()),
+                        Err(e) => Err(DeliveryError::Other(e.to_string())),
+                    },
+                    None => Err(DeliveryError::Other(String::from("encoding error"))),
+                };
+                let to_peer = String::from(Message::ALL_PEERS);
+                let event = Event::MessageResult(from_client, message_id, to_peer, result);
+                self.send_daemon_event(event).await;
+            }
+
+            // handle subscribe services request
+            Event::SubscribeServices(services) => {
+                self.subscribe_services(services);
+            }
+
+            // handle subscribe client topics request
+            Event::SubscribeTopics(topics) => {
+                self.subscribe_topics(topics);
+            }
+
+            // handle publish on client topic request
+            Event::Publish(from_client, name, content) => {
+                let topic = self.network.client_topic(&name);
+                let result = match self.swarm.behaviour_mut().gossip.publish(topic, content) {
+                    Ok(_) => Ok(()),
+                    Err(gossipsub::PublishError::NoPeersSubscribedToTopic) => {
+                        debug!("no peers subscribed to topic {}", name);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("error publishing on topic {}: {}", name, e);
+                        Err(format!("error publishing on topic {}: {}", name, e))
+                    }
+                };
+                let event = Event::PublishResult(from_client, result);
+                self.send_daemon_event(event).await;
+            }
+
+            // handle ping request
+            Event::Ping(to_peer, from_client, request_id) => {
                 let peer_id = match PeerId::from_str(&to_peer) {
                     Ok(peer_id) => peer_id,
-                    Err(_) => return,
+                    Err(_) => {
+                        let result = Err(String::from("invalid peer id"));
+                        let event = Event::PingResult(from_client, request_id, to_peer, result);
+                        self.send_daemon_event(event).await;
+                        return;
+                    }
+                };
+
+                // wait for the next round-trip time measured by the ping
+                // protocol, connect to the peer if needed
+                if !self.swarm.is_connected(&peer_id) {
+                    if let Err(e) = self.swarm.dial(peer_id) {
+                        let result = Err(format!("error dialing peer: {}", e));
+                        let event = Event::PingResult(from_client, request_id, to_peer, result);
+                        self.send_daemon_event(event).await;
+                        return;
+                    }
+                }
+                let deadline = Instant::now() + PING_TIMEOUT;
+                self.pings
+                    .entry(peer_id)
+                    .or_default()
+                    .push((from_client, request_id, deadline));
+            }
+
+            // handle block peer request
+            Event::BlockPeer(peer) => {
+                if let Ok(peer_id) = PeerId::from_str(&peer) {
+                    self.swarm.behaviour_mut().gate.block(peer_id);
+                    self.close_denied_connections();
+                }
+            }
+
+            // handle unblock peer request
+            Event::UnblockPeer(peer) => {
+                if let Ok(peer_id) = PeerId::from_str(&peer) {
+                    self.swarm.behaviour_mut().gate.unblock(&peer_id);
+                }
+            }
+
+            // handle allow peer request
+            Event::AllowPeer(peer) => {
+                if let Ok(peer_id) = PeerId::from_str(&peer) {
+                    self.swarm.behaviour_mut().gate.allow(peer_id);
+                    self.close_denied_connections();
+                }
+            }
+
+            // handle disallow peer request
+            Event::DisallowPeer(peer) => {
+                if let Ok(peer_id) = PeerId::from_str(&peer) {
+                    self.swarm.behaviour_mut().gate.disallow(&peer_id);
+                    self.close_denied_connections();
+                }
+            }
+
+            // handle get allow or deny list request
+            Event::GetGatePeers(from_client, request_id, blocked) => {
+                let gate = &self.swarm.behaviour().gate;
+                let peers = if blocked {
+                    gate.blocked().map(PeerId::to_base58).collect()
+                } else {
+                    gate.allowed().map(PeerId::to_base58).collect()
                 };
-                let msg = HiRequest::Message(to_client, from_client, service, content);
-                self.swarm
-                    .behaviour_mut()
-                    .request
-                    .send_request(&peer_id, msg);
+                let event = Event::GatePeers(from_client, request_id, blocked, peers);
+                self.send_daemon_event(event).await;
             }
 
             // events (coming from behaviour) not handled here,
             // forward to daemon
-            Event::AnnouncePeer(..) | Event::Message(..) => {
-                if let Err(e) = self.sender.send(event).await {
-                    error!("Error sending swarm event: {}", e);
-                };
+            Event::AnnouncePeer(..)
+            | Event::Message(..)
+            | Event::Relay(..)
+            | Event::TopicMessage(..)
+            | Event::NewListenAddress(..)
+            | Event::ExpiredListenAddress(..)
+            | Event::PeerIdentified(..)
+            | Event::PeerConnection(..)
+            | Event::PeerRtt(..)
+            | Event::PingResult(..)
+            | Event::MessageResult(..)
+            | Event::PublishResult(..)
+            | Event::GatePeers(..) => {
+                self.send_daemon_event(event).await;
+            }
+        }
+    }
+
+    /// send message `msg` of `from_client` with `message_id` to `to_peer`
+    /// via `next_hop`, split it into fragments if necessary and report the
+    /// result to the daemon once all requests are answered
+    async fn send_message(
+        &mut self,
+        next_hop: String,
+        to_peer: String,
+        msg: HiRequest,
+        from_client: u16,
+        message_id: u32,
+    ) {
+        let peer_id = match PeerId::from_str(&next_hop) {
+            Ok(peer_id) => peer_id,
+            Err(_) => {
+                let result = Err(DeliveryError::UnknownPeer);
+                let event = Event::MessageResult(from_client, message_id, to_peer, result);
+                self.send_daemon_event(event).await;
+                return;
+            }
+        };
+        // use the smaller frame size of both peers, peers that did not
+        // announce themselves yet only get the smallest supported frame size
+        let max_frame_size = match self.peer_frame_sizes.get(&peer_id) {
+            Some(size) => self.max_frame_size.min(*size),
+            None => fragment::MIN_FRAME_SIZE,
+        };
+        let requests = match fragment::split(msg, self.fragment_id, max_frame_size) {
+            Ok(requests) => requests,
+            Err(e) => {
+                error!("error sending message to {:?}: {}", peer_id, e);
+                let result = Err(DeliveryError::Other(e));
+                let event = Event::MessageResult(from_client, message_id, to_peer, result);
+                self.send_daemon_event(event).await;
+                return;
+            }
+        };
+        self.fragment_id = self.fragment_id.wrapping_add(1);
+
+        // track message until all requests are answered
+        let key = self.next_message;
+        self.next_message += 1;
+        let message = PendingMessage {
+            client: from_client,
+            message_id,
+            peer: peer_id,
+            destination: to_peer,
+            requests: requests.len(),
+            deadline: Instant::now() + self.send_timeout,
+        };
+        self.messages.insert(key, message);
+        for request in requests {
+            let outbound = OutboundRequest {
+                key,
+                request,
+                attempt: 0,
+            };
+            self.send_request(peer_id, outbound);
+        }
+    }
+
+    /// subscribe to broadcast topics of `services` and unsubscribe from
+    /// topics of other services
+    fn subscribe_services(&mut self, services: HashSet<u16>) {
+        let old: Vec<u16> = self
+            .service_topics
+            .values()
+            .filter(|service| !services.contains(service))
+            .cloned()
+            .collect();
+        for service in old {
+            let topic = self.network.service_topic(service);
+            self.service_topics.remove(&topic.hash());
+            self.swarm.behaviour_mut().gossip.unsubscribe(&topic);
+        }
+        for service in services {
+            let topic = self.network.service_topic(service);
+            if self.service_topics.contains_key(&topic.hash()) {
+                continue;
+            }
+            match self.swarm.behaviour_mut().gossip.subscribe(&topic) {
+                Ok(_) => {
+                    self.service_topics.insert(topic.hash(), service);
+                }
+                Err(e) => error!("error subscribing to service {}: {:?}", service, e),
+            }
+        }
+    }
+
+    /// subscribe to client topics `topics` and unsubscribe from other
+    /// client topics
+    fn subscribe_topics(&mut self, topics: HashSet<String>) {
+        let old: Vec<String> = self
+            .client_topics
+            .values()
+            .filter(|name| !topics.contains(*name))
+            .cloned()
+            .collect();
+        for name in old {
+            let topic = self.network.client_topic(&name);
+            self.client_topics.remove(&topic.hash());
+            self.swarm.behaviour_mut().gossip.unsubscribe(&topic);
+        }
+        for name in topics {
+            let topic = self.network.client_topic(&name);
+            if self.client_topics.contains_key(&topic.hash()) {
+                continue;
+            }
+            match self.swarm.behaviour_mut().gossip.subscribe(&topic) {
+                Ok(_) => {
+                    self.client_topics.insert(topic.hash(), name);
+                }
+                Err(e) => error!("error subscribing to topic {}: {:?}", name, e),
+            }
+        }
+    }
+
+    /// start listening on address `addr`
+    fn listen(&mut self, addr: &str) -> Result<(), Box<dyn Error>> {
+        let addr = addr.parse::<Multiaddr>()?;
+        if self.listeners.contains_key(&addr) {
+            return Ok(());
+        }
+        let listener = self.swarm.listen_on(addr.clone())?;
+        self.listeners.insert(addr, listener);
+        Ok(())
+    }
+
+    /// add kademlia bootstrap peer with address `addr` that must contain the peer id
+    fn add_bootstrap_peer(&mut self, addr: &str) -> Result<(), Box<dyn Error>> {
+        let addr = addr.parse::<Multiaddr>()?;
+        let peer_id = match addr.iter().last() {
+            Some(Protocol::P2p(peer_id)) => peer_id,
+            _ => return Err("missing peer id in bootstrap address".into()),
+        };
+        self.swarm.behaviour_mut().kad.add_address(&peer_id, addr);
+        Ok(())
+    }
+
+    /// close connections to peers that are not allowed by the connection gate
+    fn close_denied_connections(&mut self) {
+        let gate = &self.swarm.behaviour().gate;
+        let denied: Vec<PeerId> = self
+            .swarm
+            .connected_peers()
+            .filter(|peer_id| !gate.is_allowed(peer_id))
+            .cloned()
+            .collect();
+        for peer_id in denied {
+            debug!("closing connections to denied peer {:?}", peer_id);
+            let _ = self.swarm.disconnect_peer_id(peer_id);
+        }
+    }
+
+    /// check if peer `peer_id` is connected or there is a known address of it
+    fn is_reachable(&mut self, peer_id: &PeerId) -> bool {
+        if self.swarm.is_connected(peer_id) {
+            return true;
+        }
+        if self
+            .swarm
+            .behaviour()
+            .mdns
+            .discovered_nodes()
+            .any(|x| x == peer_id)
+        {
+            return true;
+        }
+        if self.swarm.behaviour().addresses.contains(peer_id) {
+            return true;
+        }
+        match self.swarm.behaviour_mut().kad.kbucket(*peer_id) {
+            Some(bucket) => bucket.iter().any(|e| e.node.key.preimage() == peer_id),
+            None => false,
+        }
+    }
+
+    /// send outbound request to peer `peer_id`, look up the peer's addresses
+    /// with kademlia first if it is not reachable. If the peer is not
+    /// connected, it is dialed with its known addresses
+    fn send_request(&mut self, peer_id: PeerId, outbound: OutboundRequest) {
+        if !self.is_reachable(&peer_id) {
+            debug!("looking up unknown peer {:?}", peer_id);
+            let requests = self.lookups.entry(peer_id).or_default();
+            if requests.is_empty() {
+                self.swarm.behaviour_mut().kad.get_closest_peers(peer_id);
+            }
+            requests.push(outbound);
+            return;
+        }
+        let id = self
+            .swarm
+            .behaviour_mut()
+            .request
+            .send_request(&peer_id, outbound.request.clone());
+        self.requests.insert(id, outbound);
+    }
+
+    /// schedule retransmission of failed outbound request with backoff
+    fn retry_request(&mut self, mut outbound: OutboundRequest) {
+        let backoff = RETRY_BACKOFF
+            .saturating_mul(1 << outbound.attempt.min(16))
+            .min(MAX_RETRY_BACKOFF);
+        debug!(
+            "retrying request of message {} in {:?}",
+            outbound.key, backoff
+        );
+        outbound.attempt += 1;
+        self.retries.push((Instant::now() + backoff, outbound));
+    }
+
+    /// handle result of a request of pending message `key`, report the
+    /// result of the message once all requests are answered or one failed
+    async fn handle_message_result(&mut self, key: u64, result: Result<(), DeliveryError>) {
+        let message = match self.messages.get_mut(&key) {
+            Some(message) => message,
+            None => return,
+        };
+        if result.is_ok() {
+            message.requests -= 1;
+            if message.requests > 0 {
+                return;
+            }
+        }
+        let message = self.messages.remove(&key).unwrap();
+        self.requests.retain(|_, outbound| outbound.key != key);
+        self.retries.retain(|(_, outbound)| outbound.key != key);
+        for requests in self.lookups.values_mut() {
+            requests.retain(|outbound| outbound.key != key);
+        }
+        let event = Event::MessageResult(
+            message.client,
+            message.message_id,
+            message.destination,
+            result,
+        );
+        self.send_daemon_event(event).await;
+    }
+
+    /// handle result of an outbound request, retransmit failed message
+    /// requests if `retry` is set
+    async fn handle_request_result(
+        &mut self,
+        id: request_response::OutboundRequestId,
+        result: Result<(), DeliveryError>,
+        retry: bool,
+    ) {
+        if let Some(outbound) = self.requests.remove(&id) {
+            if result.is_err() && retry && outbound.attempt < self.send_retries {
+                self.retry_request(outbound);
+                return;
+            }
+            self.handle_message_result(outbound.key, result).await;
+        }
+    }
+
+    /// forward event to the daemon
+    async fn send_daemon_event(&mut self, event: Event) {
+        if let Err(e) = self.sender.send(event).await {
+            error!("Error sending swarm event: {}", e);
+        };
+    }
+
+    /// check relay message `relay` received from `peer`: the message must
+    /// be signed by its source and the source and all peers on its path
+    /// must be allowed
+    fn check_relay(&self, peer: PeerId, relay: &RelayMessage) -> Result<(), String> {
+        if relay.path.first() != Some(&relay.from_peer)
+            || relay.path.last() != Some(&peer.to_base58())
+        {
+            return Err(String::from("invalid path"));
+        }
+        for hop in relay.path.iter() {
+            let allowed = PeerId::from_str(hop)
+                .map(|hop| self.swarm.behaviour().gate.is_allowed(&hop))
+                .unwrap_or(false);
+            if !allowed {
+                return Err(format!("peer {} is not allowed", hop));
+            }
+        }
+        relay.verify()
+    }
+
+    /// check if message `id` of source peer `peer` was received before,
+    /// remember it otherwise
+    fn is_duplicate(&mut self, peer: PeerId, id: u32) -> bool {
+        let now = Instant::now();
+        if self
+            .received
+            .get(&(peer, id))
+            .is_some_and(|expires| *expires > now)
+        {
+            return true;
+        }
+        if self.received.len() >= MAX_RECEIVED {
+            // forget the oldest message
+            let oldest = self
+                .received
+                .iter()
+                .min_by_key(|(_, expires)| **expires)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.received.remove(&oldest);
             }
         }
+        self.received.insert((peer, id), now + RECEIVED_TIMEOUT);
+        false
     }
 
     /// handle request response "request" message
@@ -97,9 +874,22 @@ impl HiSwarmHandler {
         peer: PeerId,
         request: HiRequest,
     ) -> HiResponse {
+        // drop requests from denied peers
+        if !self.swarm.behaviour().gate.is_allowed(&peer) {
+            debug!("dropping request from denied peer {:?}", peer);
+            return HiResponse::Error(String::from("peer is not allowed"));
+        }
+
         match request {
             // handle message
-            HiRequest::Message(to_client, from_client, service, content) => {
+            HiRequest::Message(to_client, from_client, service, content, id) => {
+                // drop retransmitted duplicates
+                if let Some(id) = id {
+                    if self.is_duplicate(peer, id) {
+                        debug!("dropping duplicate message {} from {:?}", id, peer);
+                        return HiResponse::Ok;
+                    }
+                }
                 debug!("received message: {:?}", content);
                 let swarm_event =
                     Event::Message(peer.to_base58(), from_client, to_client, service, content);
@@ -111,6 +901,68 @@ impl HiSwarmHandler {
                 });
                 HiResponse::Ok
             }
+
+            // handle ping of older versions, round-trip times are measured
+            // with the ping protocol now
+            HiRequest::Ping => HiResponse::Ok,
+
+            // handle relay message, forward it to the daemon for delivery
+            // or routing to the next hop
+            HiRequest::Relay(relay) => {
+                if let Err(e) = self.check_relay(peer, &relay) {
+                    debug!("dropping relay message from {:?}: {}", peer, e);
+                    return HiResponse::Error(e);
+                }
+                let own_peer = self.swarm.local_peer_id().to_base58();
+                if relay.path.contains(&own_peer) {
+                    debug!("dropping relay message from {:?}: routing loop", peer);
+                    return HiResponse::Error(String::from("routing loop"));
+                }
+                if relay.to_peer != own_peer && relay.hops == 0 {
+                    debug!("dropping relay message from {:?}: hop limit", peer);
+                    return HiResponse::Error(String::from("hop limit exceeded"));
+                }
+
+                // drop retransmitted duplicates of messages for this node
+                if let RelayContent::Message(.., id) = relay.content {
+                    let from_peer = PeerId::from_str(&relay.from_peer);
+                    if relay.to_peer == own_peer
+                        && from_peer.is_ok_and(|from_peer| self.is_duplicate(from_peer, id))
+                    {
+                        debug!("dropping duplicate relay message {} from {:?}", id, peer);
+                        return HiResponse::Ok;
+                    }
+                }
+
+                let swarm_event = Event::Relay(peer.to_base58(), relay);
+                let mut to_swarm = self.sender.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = to_swarm.send(swarm_event).await {
+                        error!("error sending event to swarm: {}", e);
+                    }
+                });
+                HiResponse::Ok
+            }
+
+            // handle fragment, handle request once all fragments are received
+            HiRequest::Fragment(id, index, count, data) => {
+                match self.reassembly.add(peer, id, index, count, data) {
+                    Ok(Some(request)) => self.handle_request_response_request(peer, request),
+                    Ok(None) => HiResponse::Ok,
+                    Err(e) => {
+                        error!("error reassembling message from {:?}: {}", peer, e);
+                        HiResponse::Error(e)
+                    }
+                }
+            }
+        }
+    }
+
+    /// reply to ping requests waiting for peer `peer_id` with `result`
+    async fn handle_ping_result(&mut self, peer_id: PeerId, result: Result<u64, String>) {
+        for (client, request_id, _) in self.pings.remove(&peer_id).unwrap_or_default() {
+            let event = Event::PingResult(client, request_id, peer_id.to_base58(), result.clone());
+            self.send_daemon_event(event).await;
         }
     }
 
@@ -147,13 +999,49 @@ impl HiSwarmHandler {
                 }
 
                 // handle incoming response message
-                request_response::Message::Response { response, .. } => {
+                request_response::Message::Response {
+                    response,
+                    request_id,
+                } => {
                     debug!("received response {:?} from {:?}", response, peer);
+                    let result = match response {
+                        HiResponse::Ok => Ok(()),
+                        HiResponse::Error(e) => Err(DeliveryError::RemoteError(e)),
+                    };
+                    self.handle_request_result(request_id, result, false).await;
                     return;
                 }
             }
         }
 
+        // handle outbound failure event
+        if let request_response::Event::OutboundFailure {
+            peer,
+            request_id,
+            ref error,
+            ..
+        } = event
+        {
+            error!("request to {:?} failed: {}", peer, error);
+            // retry requests that were never sent or lost with their
+            // connection, e.g., after an idle timeout, receivers drop
+            // duplicate messages. Timed out requests are not retried
+            let retry = matches!(
+                error,
+                request_response::OutboundFailure::DialFailure
+                    | request_response::OutboundFailure::ConnectionClosed
+                    | request_response::OutboundFailure::Io(..)
+            );
+            let error = match error {
+                request_response::OutboundFailure::DialFailure => DeliveryError::DialFailure,
+                request_response::OutboundFailure::Timeout => DeliveryError::Timeout,
+                error => DeliveryError::Other(error.to_string()),
+            };
+            self.handle_request_result(request_id, Err(error), retry)
+                .await;
+            return;
+        }
+
         // handle response sent event
         if let request_response::Event::ResponseSent {
             peer,
@@ -171,30 +1059,67 @@ impl HiSwarmHandler {
     /// handle gossipsub event
     async fn handle_gossipsub_event(&mut self, event: gossipsub::Event) {
         match event {
-            gossipsub::Event::Message { message, .. } => match HiAnnounce::decode(&message.data) {
-                Some(msg) => {
-                    debug!(
-                        "Message: {:?} -> {:?}: {:?}",
-                        message.source, message.topic, msg
-                    );
-                    if let Some(peer) = message.source {
-                        let swarm_event =
-                            Event::AnnouncePeer(peer.to_string(), msg.name, msg.services_tag);
-                        let mut to_swarm = self.sender.clone();
-                        tokio::spawn(async move {
-                            if let Err(e) = to_swarm.send(swarm_event).await {
-                                error!("error sending event to swarm: {}", e);
+            gossipsub::Event::Message {
+                message_id,
+                message,
+                ..
+            } => {
+                if message.topic != self.network.topic.hash() {
+                    self.handle_topic_message(message_id, message).await;
+                    return;
+                }
+                match HiAnnounce::decode(&message.data) {
+                    Some(msg) => {
+                        debug!(
+                            "Message: {:?} -> {:?}: {:?}",
+                            message.source, message.topic, msg
+                        );
+                        if let Some(peer) = message.source {
+                            // drop announcements of denied peers
+                            if !self.swarm.behaviour().gate.is_allowed(&peer) {
+                                debug!("dropping announcement of denied peer {:?}", peer);
+                                return;
+                            }
+                            // drop announcements with unsupported version
+                            if !msg.is_supported() {
+                                error!(
+                                    "dropping announcement of peer {:?} with unsupported version {}",
+                                    peer, msg.version
+                                );
+                                return;
                             }
-                        });
+                            // remember frame size of connected peers, older
+                            // versions do not announce it and use the default
+                            if self.swarm.is_connected(&peer) {
+                                let size = match msg.max_frame_size {
+                                    Some(size) => (size as usize).max(fragment::MIN_FRAME_SIZE),
+                                    None => request::DEFAULT_MAX_FRAME_SIZE,
+                                };
+                                self.peer_frame_sizes.insert(peer, size);
+                            }
+                            let swarm_event = Event::AnnouncePeer(
+                                peer.to_string(),
+                                msg.name,
+                                msg.services_tag,
+                                msg.version,
+                                msg.neighbors.unwrap_or_default(),
+                            );
+                            let mut to_swarm = self.sender.clone();
+                            tokio::spawn(async move {
+                                if let Err(e) = to_swarm.send(swarm_event).await {
+                                    error!("error sending event to swarm: {}", e);
+                                }
+                            });
+                        }
+                    }
+                    None => {
+                        debug!(
+                            "Message: {:?} -> {:?}: {:?}",
+                            message.source, message.topic, message.data
+                        );
                     }
                 }
-                None => {
-                    debug!(
-                        "Message: {:?} -> {:?}: {:?}",
-                        message.source, message.topic, message.data
-                    );
-                }
-            },
+            }
             gossipsub::Event::Subscribed { peer_id, topic } => {
                 debug!("Subscribed: {:?} {:?}", peer_id, topic);
             }
@@ -210,12 +1135,59 @@ impl HiSwarmHandler {
         }
     }
 
+    /// handle message received on the gossipsub topic of a service or
+    /// client topic
+    async fn handle_topic_message(
+        &mut self,
+        message_id: gossipsub::MessageId,
+        message: gossipsub::Message,
+    ) {
+        let peer = match message.source {
+            Some(peer) => peer,
+            None => return,
+        };
+
+        // drop duplicates and messages of denied peers
+        if self.seen.contains_key(&message_id) {
+            debug!("dropping duplicate topic message {}", message_id);
+            return;
+        }
+        self.seen.insert(message_id, Instant::now() + SEEN_TIMEOUT);
+        if !self.swarm.behaviour().gate.is_allowed(&peer) {
+            debug!("dropping topic message of denied peer {:?}", peer);
+            return;
+        }
+
+        // handle broadcast message of a service
+        if let Some(service) = self.service_topics.get(&message.topic) {
+            if let Some(msg) = HiBroadcast::decode(&message.data) {
+                let event = Event::Message(
+                    peer.to_base58(),
+                    msg.from_client,
+                    msg.to_client,
+                    *service,
+                    msg.content,
+                );
+                self.send_daemon_event(event).await;
+            }
+            return;
+        }
+
+        // handle message on client topic
+        if let Some(name) = self.client_topics.get(&message.topic) {
+            let event = Event::TopicMessage(name.clone(), peer.to_base58(), message.data);
+            self.send_daemon_event(event).await;
+        }
+    }
+
     /// handle mdns event
     async fn handle_mdns_event(&mut self, event: mdns::Event) {
         match event {
             mdns::Event::Discovered(list) => {
                 for (peer, addr) in list {
                     debug!("Peer discovered: {:?} {:?}", peer, addr);
+                    self.swarm.behaviour_mut().addresses.add(peer, addr.clone());
+                    self.swarm.behaviour_mut().kad.add_address(&peer, addr);
                 }
             }
             mdns::Event::Expired(list) => {
@@ -234,6 +1206,132 @@ impl HiSwarmHandler {
         }
     }
 
+    /// handle result of a kademlia lookup of a peer
+    async fn handle_kademlia_lookup(&mut self, key: Vec<u8>, peers: Vec<kad::PeerInfo>) {
+        let peer_id = match PeerId::from_bytes(&key) {
+            Ok(peer_id) => peer_id,
+            Err(_) => return,
+        };
+        let requests = match self.lookups.remove(&peer_id) {
+            Some(requests) => requests,
+            None => return,
+        };
+
+        // add found addresses and send waiting requests
+        let peer = match peers.into_iter().find(|p| p.peer_id == peer_id) {
+            Some(peer) => peer,
+            None => {
+                error!("could not find peer {:?}, dropping messages", peer_id);
+                for outbound in requests {
+                    self.handle_message_result(outbound.key, Err(DeliveryError::UnknownPeer))
+                        .await;
+                }
+                return;
+            }
+        };
+        for addr in peer.addrs {
+            self.swarm.behaviour_mut().kad.add_address(&peer_id, addr);
+        }
+        for outbound in requests {
+            let id = self
+                .swarm
+                .behaviour_mut()
+                .request
+                .send_request(&peer_id, outbound.request.clone());
+            self.requests.insert(id, outbound);
+        }
+    }
+
+    /// handle kademlia event
+    async fn handle_kademlia_event(&mut self, event: kad::Event) {
+        match event {
+            kad::Event::OutboundQueryProgressed {
+                result: kad::QueryResult::GetClosestPeers(result),
+                ..
+            } => match result {
+                Ok(kad::GetClosestPeersOk { key, peers }) => {
+                    self.handle_kademlia_lookup(key, peers).await;
+                }
+                Err(kad::GetClosestPeersError::Timeout { key, peers }) => {
+                    self.handle_kademlia_lookup(key, peers).await;
+                }
+            },
+            kad::Event::RoutingUpdated { peer, .. } => {
+                debug!("Kademlia routing updated: {:?}", peer);
+            }
+            event => debug!("Kademlia: {:?}", event),
+        }
+    }
+
+    /// handle identify event
+    async fn handle_identify_event(&mut self, event: identify::Event) {
+        match event {
+            identify::Event::Received { peer_id, info, .. } => {
+                debug!("Peer identified: {:?} {:?}", peer_id, info);
+
+                // add peer's listen addresses to kademlia,
+                // remove peers in other networks from kademlia
+                if info.protocols.contains(&self.network.kad_protocol) {
+                    for addr in info.listen_addrs.iter() {
+                        self.swarm
+                            .behaviour_mut()
+                            .kad
+                            .add_address(&peer_id, addr.clone());
+                    }
+                } else {
+                    debug!("Peer {:?} is not in our network", peer_id);
+                    self.swarm.behaviour_mut().kad.remove_peer(&peer_id);
+                }
+
+                // get request protocol versions supported by the peer
+                let request_versions: Vec<String> = self
+                    .network
+                    .request_protocols
+                    .iter()
+                    .filter(|p| info.protocols.iter().any(|x| x.as_ref() == p.as_ref()))
+                    .map(|p| p.version().to_string())
+                    .collect();
+
+                // remember peers that can relay messages
+                if request_versions.iter().any(|v| v == request::RELAY_VERSION) {
+                    self.relay_peers.insert(peer_id);
+                } else {
+                    self.relay_peers.remove(&peer_id);
+                }
+
+                // forward peer information to daemon
+                let event = Event::PeerIdentified(
+                    peer_id.to_base58(),
+                    info.agent_version,
+                    info.protocol_version,
+                    info.listen_addrs.iter().map(|a| a.to_string()).collect(),
+                    info.observed_addr.to_string(),
+                    request_versions,
+                );
+                self.send_daemon_event(event).await;
+            }
+            event => debug!("Identify: {:?}", event),
+        }
+    }
+
+    /// handle ping event
+    async fn handle_ping_event(&mut self, event: ping::Event) {
+        match event.result {
+            Ok(rtt) => {
+                debug!("Ping: {:?} {:?}", event.peer, rtt);
+                let rtt = rtt.as_micros() as u64;
+                self.handle_ping_result(event.peer, Ok(rtt)).await;
+                let event = Event::PeerRtt(event.peer.to_base58(), rtt);
+                self.send_daemon_event(event).await;
+            }
+            Err(e) => {
+                debug!("Ping error: {:?} {}", event.peer, e);
+                self.handle_ping_result(event.peer, Err(format!("ping error: {}", e)))
+                    .await;
+            }
+        }
+    }
+
     /// handle swarm event
     async fn handle_swarm_event(&mut self, event: SwarmEvent<HiBehaviourEvent>) {
         match event {
@@ -252,12 +1350,82 @@ impl HiSwarmHandler {
                 self.handle_mdns_event(event).await;
             }
 
+            // kademlia event
+            SwarmEvent::Behaviour(HiBehaviourEvent::Kademlia(event)) => {
+                self.handle_kademlia_event(event).await;
+            }
+
+            // identify event
+            SwarmEvent::Behaviour(HiBehaviourEvent::Identify(event)) => {
+                self.handle_identify_event(event).await;
+            }
+
+            // ping event
+            SwarmEvent::Behaviour(HiBehaviourEvent::Ping(event)) => {
+                self.handle_ping_event(event).await;
+            }
+
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                num_established,
+                ..
+            } => {
+                debug!("Connection established: {:?}", peer_id);
+                if num_established.get() == 1 {
+                    let event = Event::PeerConnection(peer_id.to_base58(), true);
+                    self.send_daemon_event(event).await;
+                }
+            }
+
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established,
+                ..
+            } => {
+                debug!("Connection closed: {:?}", peer_id);
+                if num_established == 0 {
+                    self.relay_peers.remove(&peer_id);
+                    self.peer_frame_sizes.remove(&peer_id);
+                    let event = Event::PeerConnection(peer_id.to_base58(), false);
+                    self.send_daemon_event(event).await;
+                }
+            }
+
+            SwarmEvent::OutgoingConnectionError {
+                peer_id: Some(peer_id),
+                error,
+                ..
+            } => {
+                debug!("Outgoing connection error: {:?} {}", peer_id, error);
+                if !self.swarm.is_connected(&peer_id) {
+                    let result = Err(format!("error connecting to peer: {}", error));
+                    self.handle_ping_result(peer_id, result).await;
+                }
+            }
+
             SwarmEvent::NewListenAddr { address, .. } => {
                 println!("Started listening on {:?}", address);
+                let event = Event::NewListenAddress(address.to_string());
+                self.send_daemon_event(event).await;
             }
 
             SwarmEvent::ExpiredListenAddr { address, .. } => {
                 println!("Stopped listening on {:?}", address);
+                let event = Event::ExpiredListenAddress(address.to_string());
+                self.send_daemon_event(event).await;
+            }
+
+            SwarmEvent::ListenerClosed {
+                listener_id,
+                addresses,
+                ..
+            } => {
+                self.listeners.retain(|_, listener| *listener != listener_id);
+                for address in addresses {
+                    println!("Stopped listening on {:?}", address);
+                    let event = Event::ExpiredListenAddress(address.to_string());
+                    self.send_daemon_event(event).await;
+                }
             }
 
             event => debug!("{:?}", event),
@@ -266,8 +1434,14 @@ impl HiSwarmHandler {
 
     /// handle timer event
     async fn handle_timer_event(&mut self) {
+        // drop incomplete fragmented messages and old deduplication entries
+        self.reassembly.expire();
+        let now = Instant::now();
+        self.seen.retain(|_, expires| *expires > now);
+        self.received.retain(|_, expires| *expires > now);
+
         // check number of peers in gossipsub
-        let topic = gossipsub::IdentTopic::new("/hello/world");
+        let topic = self.network.topic.clone();
         if self
             .swarm
             .behaviour()
@@ -278,7 +1452,7 @@ impl HiSwarmHandler {
         {
             debug!("No nodes in mesh");
 
-            // get peerids of discovered peers
+            // get peerids of discovered peers and peers in kademlia routing table
             let mut peer_ids: Vec<PeerId> = Vec::new();
             for peer_id in self.swarm.behaviour().mdns.discovered_nodes() {
                 if peer_ids.contains(peer_id) {
@@ -286,8 +1460,18 @@ impl HiSwarmHandler {
                 }
                 peer_ids.push(peer_id.clone());
             }
+            for bucket in self.swarm.behaviour_mut().kad.kbuckets() {
+                for entry in bucket.iter() {
+                    let peer_id = entry.node.key.preimage();
+                    if peer_ids.contains(peer_id) {
+                        continue;
+                    }
+                    peer_ids.push(*peer_id);
+                }
+            }
 
-            // try connecting to discovered peers
+            // try connecting to discovered peers that are allowed
+            peer_ids.retain(|peer_id| self.swarm.behaviour().gate.is_allowed(peer_id));
             for peer_id in peer_ids {
                 match self.swarm.dial(peer_id) {
                     Ok(_) => (),
@@ -296,10 +1480,18 @@ impl HiSwarmHandler {
             }
         }
 
-        // announce presence
+        // announce presence and connected peers that can relay messages
         let mut announce = HiAnnounce::new();
         announce.name = self.node_name.to_string();
         announce.services_tag = self.services_tag;
+        announce.max_frame_size = Some(u32::try_from(self.max_frame_size).unwrap_or(u32::MAX));
+        announce.neighbors = Some(
+            self.swarm
+                .connected_peers()
+                .filter(|peer_id| self.relay_peers.contains(peer_id))
+                .map(|peer_id| peer_id.to_base58())
+                .collect(),
+        );
         if let Some(announce) = announce.encode() {
             match self.swarm.behaviour_mut().gossip.publish(topic, announce) {
                 Ok(_) => (),
@@ -308,10 +1500,58 @@ impl HiSwarmHandler {
         }
     }
 
+    /// handle retry timer event: fail timed out messages and ping requests
+    /// and retransmit failed requests
+    async fn handle_retry_timer_event(&mut self) {
+        // fail messages that timed out
+        let now = Instant::now();
+        let expired: Vec<u64> = self
+            .messages
+            .iter()
+            .filter(|(_, message)| message.deadline <= now)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            self.handle_message_result(key, Err(DeliveryError::Timeout))
+                .await;
+        }
+
+        // fail ping requests that timed out
+        let mut expired = Vec::new();
+        for (peer_id, pings) in self.pings.iter_mut() {
+            pings.retain(|&(client, request_id, deadline)| {
+                if deadline > now {
+                    return true;
+                }
+                expired.push((client, request_id, *peer_id));
+                false
+            });
+        }
+        self.pings.retain(|_, pings| !pings.is_empty());
+        for (client, request_id, peer_id) in expired {
+            let result = Err(String::from("ping timed out"));
+            let event = Event::PingResult(client, request_id, peer_id.to_base58(), result);
+            self.send_daemon_event(event).await;
+        }
+
+        // retransmit requests
+        let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.retries)
+            .into_iter()
+            .partition(|(time, _)| *time <= now);
+        self.retries = waiting;
+        for (_, outbound) in due {
+            if let Some(message) = self.messages.get(&outbound.key) {
+                let peer_id = message.peer;
+                self.send_request(peer_id, outbound);
+            }
+        }
+    }
+
     /// main loop for handling events
     async fn handle_events(&mut self) {
         let timer = time::sleep(Duration::new(5, 0));
         tokio::pin!(timer);
+        let mut retry_timer = time::interval(Duration::from_secs(1));
 
         loop {
             tokio::select! {
@@ -330,6 +1570,11 @@ impl HiSwarmHandler {
                     self.handle_swarm_event(event).await;
                 },
 
+                // handle retry timer events
+                _ = retry_timer.tick() => {
+                    self.handle_retry_timer_event().await;
+                },
+
                 // handle timer events
                 event = &mut timer => {
                     debug!("timer event: {:?}", event);
@@ -341,74 +1586,204 @@ impl HiSwarmHandler {
     }
 }
 
+/// create network behaviour for node identity `key` in network `network`
+/// with maximum frame size `max_frame_size`
+fn create_behaviour(
+    key: &identity::Keypair,
+    network: &Network,
+    max_frame_size: usize,
+) -> Result<HiBehaviour, Box<dyn Error + Send + Sync>> {
+    // create mdns
+    let mdns = mdns::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?;
+
+    // create gossip
+    let message_authenticity = gossipsub::MessageAuthenticity::Signed(key.clone());
+    let gossipsub_config = gossipsub::Config::default();
+    let mut gossip = gossipsub::Behaviour::new(message_authenticity, gossipsub_config)?;
+
+    // subscribe to topic
+    gossip.subscribe(&network.topic)?;
+
+    // create request-response with all supported protocol versions
+    let protocols = network
+        .request_protocols
+        .iter()
+        .map(|protocol| (protocol.clone(), request_response::ProtocolSupport::Full));
+    let cfg = request_response::Config::default();
+    let codec = HiCodec::new(max_frame_size);
+    let request = request_response::Behaviour::with_codec(codec, protocols, cfg);
+
+    // create kademlia, always answer requests from other peers
+    let peer_id = key.public().to_peer_id();
+    let store = kad::store::MemoryStore::new(peer_id);
+    let mut kad = kad::Behaviour::with_config(
+        peer_id,
+        store,
+        kad::Config::new(network.kad_protocol.clone()),
+    );
+    kad.set_mode(Some(kad::Mode::Server));
+
+    // create identify
+    let identify_config =
+        identify::Config::new(IDENTIFY_PROTOCOL_VERSION.to_string(), key.public())
+            .with_agent_version(format!("hi/{}", env!("CARGO_PKG_VERSION")));
+    let identify = identify::Behaviour::new(identify_config);
+
+    // create ping
+    let ping = ping::Behaviour::new(ping::Config::new());
+
+    // create network behaviour
+    let behaviour = HiBehaviour {
+        gate: Gate::default(),
+        addresses: AddressBook::default(),
+        request,
+        gossip,
+        mdns,
+        kad,
+        identify,
+        ping,
+    };
+
+    Ok(behaviour)
+}
+
+/// create swarm configuration
+fn swarm_config(cfg: libp2p::swarm::Config) -> libp2p::swarm::Config {
+    cfg.with_idle_connection_timeout(Duration::from_secs(5))
+}
+
 /// Hi swarm
 pub struct HiSwarm {
     sender: Sender<Event>,
     receiver: Receiver<Event>,
+    peer_id: PeerId,
 }
 
 impl HiSwarm {
-    /// create and run swarm
-    pub async fn run() -> Result<Self, Box<dyn Error>> {
-        // create swarm
-        let mut swarm = SwarmBuilder::with_new_identity()
-            .with_tokio()
-            .with_tcp(
-                Default::default(),
-                (libp2p::tls::Config::new, libp2p::noise::Config::new),
-                libp2p::yamux::Config::default,
-            )?
-            .with_dns()?
-            .with_behaviour(|key| {
-                // create mdns
-                let mdns =
-                    mdns::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?;
-
-                // create gossip
-                let message_authenticity = gossipsub::MessageAuthenticity::Signed(key.clone());
-                let gossipsub_config = gossipsub::Config::default();
-                let mut gossip = gossipsub::Behaviour::new(message_authenticity, gossipsub_config)?;
-
-                // subscribe to topic
-                let topic = gossipsub::IdentTopic::new(TOPIC);
-                gossip.subscribe(&topic).unwrap();
-
-                // create request-response
-                let protocols =
-                    iter::once((HiRequestProtocol(), request_response::ProtocolSupport::Full));
-                let cfg = request_response::Config::default();
-                let request = request_response::Behaviour::new(protocols.clone(), cfg.clone());
-
-                // create network behaviour
-                let behaviour = HiBehaviour {
-                    request,
-                    gossip,
-                    mdns,
-                };
+    /// create and run swarm with `options`
+    pub async fn run(options: Options) -> Result<Self, Box<dyn Error>> {
+        // create swarm with configured transports in configured network
+        let network = Network::new(&options.network)?;
+        let max_frame_size = options.max_frame_size;
+        if max_frame_size < fragment::MIN_FRAME_SIZE {
+            return Err(format!("maximum frame size {} too small", max_frame_size).into());
+        }
+        let keypair = options.keypair;
+        let builder = SwarmBuilder::with_existing_identity(keypair.clone()).with_tokio();
+        let mut swarm = match (options.transport, options.psk) {
+            // private network: TCP with pre-shared key, noise and yamux
+            (Transport::Tcp, Some(psk)) => {
+                let noise_config = noise::Config::new(&keypair)?;
+                builder
+                    .with_other_transport(|_| {
+                        tcp::tokio::Transport::new(tcp::Config::default())
+                            .and_then(move |socket, _| PnetConfig::new(psk).handshake(socket))
+                            .upgrade(Version::V1Lazy)
+                            .authenticate(noise_config)
+                            .multiplex(yamux::Config::default())
+                    })?
+                    .with_dns()?
+                    .with_behaviour(|key| create_behaviour(key, &network, max_frame_size))?
+                    .with_swarm_config(swarm_config)
+                    .build()
+            }
+            (_, Some(_)) => {
+                return Err("private network only supports TCP transport".into());
+            }
+            (Transport::Tcp, None) => builder
+                .with_tcp(
+                    Default::default(),
+                    (libp2p::tls::Config::new, libp2p::noise::Config::new),
+                    libp2p::yamux::Config::default,
+                )?
+                .with_dns()?
+                .with_behaviour(|key| create_behaviour(key, &network, max_frame_size))?
+                .with_swarm_config(swarm_config)
+                .build(),
+            (Transport::Quic, None) => builder
+                .with_quic()
+                .with_dns()?
+                .with_behaviour(|key| create_behaviour(key, &network, max_frame_size))?
+                .with_swarm_config(swarm_config)
+                .build(),
+            (Transport::All, None) => builder
+                .with_tcp(
+                    Default::default(),
+                    (libp2p::tls::Config::new, libp2p::noise::Config::new),
+                    libp2p::yamux::Config::default,
+                )?
+                .with_quic()
+                .with_dns()?
+                .with_behaviour(|key| create_behaviour(key, &network, max_frame_size))?
+                .with_swarm_config(swarm_config)
+                .build(),
+        };
+        let peer_id = *swarm.local_peer_id();
+        println!("Local peer id: {:?}", peer_id);
 
-                Ok(behaviour)
-            })?
-            .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(5)))
-            .build();
-        println!("Local peer id: {:?}", swarm.local_peer_id());
+        // configure allowed and blocked peers before connecting to anyone
+        for peer in options.allow.iter() {
+            let peer_id = PeerId::from_str(peer)?;
+            swarm.behaviour_mut().gate.allow(peer_id);
+        }
+        for peer in options.block.iter() {
+            let peer_id = PeerId::from_str(peer)?;
+            swarm.behaviour_mut().gate.block(peer_id);
+        }
 
         // create channel for sending/receiving events to/from the swarm
         let (to_swarm_sender, to_swarm_receiver) = mpsc::unbounded();
         let (from_swarm_sender, from_swarm_receiver) = mpsc::unbounded();
 
-        // listen on all IPs and random ports.
-        swarm.listen_on("/ip6/::/tcp/0".parse()?)?;
-        swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+        // create handler
+        let mut handler = HiSwarmHandler {
+            swarm,
+            keypair,
+            receiver: to_swarm_receiver,
+            sender: from_swarm_sender,
+            network,
+            node_name: String::from(""),
+            services_tag: 0,
+            listeners: HashMap::new(),
+            relay_peers: HashSet::new(),
+            service_topics: HashMap::new(),
+            client_topics: HashMap::new(),
+            seen: HashMap::new(),
+            received: HashMap::new(),
+            lookups: HashMap::new(),
+            messages: HashMap::new(),
+            requests: HashMap::new(),
+            retries: Vec::new(),
+            next_message: 0,
+            send_timeout: options.send_timeout,
+            send_retries: options.send_retries,
+            pings: HashMap::new(),
+            max_frame_size,
+            peer_frame_sizes: HashMap::new(),
+            fragment_id: 0,
+            reassembly: Reassembly::default(),
+        };
+
+        // listen on configured addresses or all IPs and random ports
+        let listen = if options.listen.is_empty() {
+            options.transport.default_listen_addresses()
+        } else {
+            options.listen
+        };
+        for addr in listen {
+            handler.listen(&addr)?;
+        }
+
+        // add kademlia bootstrap peers and join the DHT
+        for addr in options.bootstrap.iter() {
+            handler.add_bootstrap_peer(addr)?;
+        }
+        if !options.bootstrap.is_empty() {
+            handler.swarm.behaviour_mut().kad.bootstrap()?;
+        }
 
         // start main loop
-        tokio::spawn(async {
-            let mut handler = HiSwarmHandler {
-                swarm,
-                receiver: to_swarm_receiver,
-                sender: from_swarm_sender,
-                node_name: String::from(""),
-                services_tag: 0,
-            };
+        tokio::spawn(async move {
             handler.handle_events().await;
             debug!("swarm stopped");
         });
@@ -416,9 +1791,15 @@ impl HiSwarm {
         Ok(HiSwarm {
             sender: to_swarm_sender,
             receiver: from_swarm_receiver,
+            peer_id,
         })
     }
 
+    /// get local peer id
+    pub fn peer_id(&self) -> String {
+        self.peer_id.to_base58()
+    }
+
     /// send event to the swarm
     pub async fn send(&mut self, event: Event) {
         if let Err(e) = self.sender.send(event).await {
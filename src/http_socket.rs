@@ -0,0 +1,450 @@
+use crate::api;
+use crate::config::Config;
+use crate::message::{Event, GetSet, Message, Service};
+use crate::tcp_socket;
+use futures::future::FutureExt;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::{self, timeout, Duration};
+
+/// maximum size of the request line and headers
+const MAX_HEADER_SIZE: u64 = 8 * 1024;
+
+/// maximum size of a request body
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// time to wait for the request of a client
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// interval of keep-alive comments in the event stream
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// maximum number of concurrent http clients
+const MAX_CLIENTS: usize = 64;
+
+/// parse listen address of the http api, a port number only binds to
+/// localhost
+pub fn parse_address(address: &str) -> Result<SocketAddr, String> {
+    if let Ok(port) = address.parse::<u16>() {
+        return Ok(SocketAddr::from((Ipv4Addr::LOCALHOST, port)));
+    }
+    address
+        .parse()
+        .map_err(|_| format!("invalid http address: {}", address))
+}
+
+/// HTTP request
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// read request line and headers from `reader`, the body is read
+    /// separately with `read_body()`
+    async fn read_header<R>(reader: &mut R) -> io::Result<Self>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        // read request line and headers
+        let mut header = reader.take(MAX_HEADER_SIZE);
+        let mut line = String::new();
+        header.read_line(&mut line).await?;
+        let mut parts = line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method.to_string(), target),
+            _ => return Err(invalid_request("invalid request line")),
+        };
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), query.to_string()),
+            None => (target.to_string(), String::new()),
+        };
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            if header.read_line(&mut line).await? == 0 {
+                return Err(invalid_request("incomplete request header"));
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
+
+        Ok(Request {
+            method,
+            path,
+            query,
+            headers,
+            body: Vec::new(),
+        })
+    }
+
+    /// get value of query parameter `name`
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value)
+    }
+
+    /// read body from `reader`
+    async fn read_body<R>(&mut self, reader: &mut R) -> io::Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let length = match self.headers.get("content-length") {
+            Some(length) => match length.parse::<usize>() {
+                Ok(length) if length <= MAX_BODY_SIZE => length,
+                _ => return Err(invalid_request("invalid content length")),
+            },
+            None => 0,
+        };
+        self.body = vec![0; length];
+        reader.read_exact(&mut self.body).await?;
+        Ok(())
+    }
+
+    /// check if the request carries the bearer `token`
+    fn is_authorized(&self, token: &str) -> bool {
+        match self.headers.get("authorization") {
+            Some(authorization) => match authorization.strip_prefix("Bearer ") {
+                Some(bearer) => tcp_socket::check_token(token, bearer),
+                None => false,
+            },
+            None => false,
+        }
+    }
+}
+
+/// create error for invalid requests
+fn invalid_request(error: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// HTTP response
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    /// create response with `status` and `body` serialized as JSON
+    fn json<T: Serialize>(status: u16, body: &T) -> Self {
+        match serde_json::to_string(body) {
+            Ok(body) => Response { status, body },
+            Err(e) => Self::error(500, &e.to_string()),
+        }
+    }
+
+    /// create error response with `status` and `error` message
+    fn error(status: u16, error: &str) -> Self {
+        let body = serde_json::json!({ "error": error }).to_string();
+        Response { status, body }
+    }
+
+    /// create response from reply `content` of a get or set request
+    fn reply(content: GetSet) -> Self {
+        match content {
+            GetSet::Error(e) => Self::error(400, &e),
+            content => Self::json(200, &content),
+        }
+    }
+
+    /// write response to `writer`
+    async fn write<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let header = format!(
+            "HTTP/1.1 {} {}\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.body.len() + 1,
+        );
+        writer.write_all(header.as_bytes()).await?;
+        writer.write_all(self.body.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+        Ok(())
+    }
+}
+
+/// get reason phrase of http `status` code
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+/// HTTP server, translates requests to messages on the daemon's unix socket
+pub struct HttpServer {
+    listener: TcpListener,
+    dir: PathBuf,
+    token: String,
+}
+
+impl HttpServer {
+    /// Listen on loopback `address`
+    pub async fn listen(config: &Config, address: SocketAddr) -> io::Result<Self> {
+        if !address.ip().is_loopback() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("address {} is not a loopback address", address),
+            ));
+        }
+        let token = tcp_socket::load_token(config).await?;
+        let listener = TcpListener::bind(address).await?;
+        Ok(HttpServer {
+            listener,
+            dir: config.dir.clone().unwrap(),
+            token,
+        })
+    }
+
+    /// Accept clients and handle them until an error occurs, clients beyond
+    /// the maximum number of concurrent clients are disconnected
+    pub async fn run(self) {
+        let clients = Arc::new(Semaphore::new(MAX_CLIENTS));
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, address)) => {
+                    let permit = match clients.clone().try_acquire_owned() {
+                        Ok(permit) => permit,
+                        Err(_) => {
+                            debug!("too many http clients, rejecting {}", address);
+                            continue;
+                        }
+                    };
+                    debug!("http client {} connected", address);
+                    let client = HttpClient {
+                        dir: self.dir.clone(),
+                        token: self.token.clone(),
+                    };
+                    tokio::spawn(async move {
+                        client.run(stream).await;
+                        drop(permit);
+                    });
+                }
+                Err(e) => {
+                    error!("http server error: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// HTTP client
+struct HttpClient {
+    dir: PathBuf,
+    token: String,
+}
+
+impl HttpClient {
+    /// connect to the daemon and register with `services`
+    async fn connect(&self, services: HashSet<u16>) -> io::Result<api::Client> {
+        let mut client = api::Client::connect(&self.dir).await?;
+        client.register(services).await?;
+        Ok(client)
+    }
+
+    /// handle "GET /peers" and "GET /name" requests
+    async fn handle_get(&self, content: GetSet) -> io::Result<Response> {
+        let mut client = self.connect(HashSet::new()).await?;
+        Ok(Response::reply(client.get(content).await?))
+    }
+
+    /// handle "POST /connect" request with the address in the body
+    async fn handle_connect(&self, request: &Request) -> io::Result<Response> {
+        let address = match std::str::from_utf8(&request.body) {
+            Ok(address) => address.trim().to_string(),
+            Err(_) => return Ok(Response::error(400, "invalid address")),
+        };
+        let mut client = self.connect(HashSet::new()).await?;
+        Ok(Response::reply(client.set(GetSet::Connect(address)).await?))
+    }
+
+    /// handle "POST /messages/{peer}/{client}/{service}" request with the
+    /// content of the message in the body
+    async fn handle_message(&self, path: &str, content: Vec<u8>) -> io::Result<Response> {
+        let parts: Vec<&str> = path.split('/').skip(2).collect();
+        let (peer, to_client, service) = match parts[..] {
+            [peer, to_client, service] => match (to_client.parse(), service.parse()) {
+                (Ok(to_client), Ok(service)) => (peer, to_client, service),
+                _ => return Ok(Response::error(400, "invalid client or service")),
+            },
+            _ => return Ok(Response::error(404, "not found")),
+        };
+        let mut client = self.connect(HashSet::new()).await?;
+        let message_id = client
+            .send_message(peer, to_client, service, content)
+            .await?;
+        let body = serde_json::json!({ "message_id": message_id });
+        Ok(Response::json(202, &body))
+    }
+
+    /// handle "GET /events" request, stream events until the client
+    /// disconnects. Service updates are only sent for the services in the
+    /// "services" query parameter, e.g., "/events?services=1025,1026"
+    async fn handle_events<W>(&self, request: &Request, writer: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut services: HashSet<u16> = HashSet::new();
+        if let Some(list) = request.query_param("services") {
+            for service in list.split(',') {
+                let Ok(service) = service.parse() else {
+                    let response = Response::error(400, "invalid services");
+                    return response.write(writer).await;
+                };
+                services.insert(service);
+            }
+        }
+
+        // register as service client to receive peer and client updates,
+        // messages are read in a separate task, so receiving them in select
+        // is cancel safe. Dropping the sender closes the connection
+        services.insert(Service::Service as u16);
+        let (receiver, _sender) = self.connect(services).await?.into_split();
+
+        let header = "HTTP/1.1 200 OK\r\n\
+            Content-Type: text/event-stream\r\n\
+            Cache-Control: no-cache\r\n\
+            Connection: close\r\n\r\n";
+        writer.write_all(header.as_bytes()).await?;
+
+        // the response is started, so errors only end the stream
+        if let Err(e) = Self::stream_events(receiver, writer).await {
+            debug!("http event stream stopped: {}", e);
+        }
+        Ok(())
+    }
+
+    /// forward peer, client and service updates from `receiver` to `writer`
+    /// as server-sent events until an error occurs
+    async fn stream_events<W>(mut receiver: api::Receiver, writer: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut keepalive = time::interval(KEEPALIVE_INTERVAL);
+        loop {
+            tokio::select! {
+                // forward events from the daemon
+                msg = receiver.receive().fuse() => {
+                    let event = match msg? {
+                        Message::Event { event, .. } => event,
+                        _ => continue,
+                    };
+                    let name = match event {
+                        Event::PeerUpdate(..) => "peer",
+                        Event::ClientUpdate(..) => "client",
+                        Event::ServiceUpdate(..) => "service",
+                        _ => continue,
+                    };
+                    let data = serde_json::to_string(&event)?;
+                    let event = format!("event: {}\ndata: {}\n\n", name, data);
+                    writer.write_all(event.as_bytes()).await?;
+                },
+
+                // send keep-alive comment, detects disconnected clients
+                _ = keepalive.tick().fuse() => {
+                    writer.write_all(b": keep-alive\n\n").await?;
+                },
+            }
+        }
+    }
+
+    /// handle request
+    async fn handle_request<W>(&self, request: Request, writer: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let response = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/events") => return self.handle_events(&request, writer).await,
+            ("GET", "/peers") => self.handle_get(GetSet::Peers(Vec::new())).await?,
+            ("GET", "/name") => self.handle_get(GetSet::Name(String::new())).await?,
+            ("POST", "/connect") => self.handle_connect(&request).await?,
+            ("POST", path) if path.starts_with("/messages/") => {
+                self.handle_message(path, request.body).await?
+            }
+            (_, "/events" | "/peers" | "/name" | "/connect") => {
+                Response::error(405, "method not allowed")
+            }
+            _ => Response::error(404, "not found"),
+        };
+        response.write(writer).await
+    }
+
+    /// read request from `reader`, the body is only read if the client
+    /// sent the correct `token`, unauthorized requests are returned without
+    /// body
+    async fn read_request<R>(reader: &mut R, token: &str) -> io::Result<Request>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let mut request = Request::read_header(reader).await?;
+        if request.is_authorized(token) {
+            request.read_body(reader).await?;
+        }
+        Ok(request)
+    }
+
+    /// Handle client connected with `stream`: read its request and send
+    /// the response
+    async fn run(self, mut stream: TcpStream) {
+        let (reader, mut writer) = stream.split();
+        let mut reader = BufReader::new(reader);
+        let read = Self::read_request(&mut reader, &self.token);
+        let request = match timeout(REQUEST_TIMEOUT, read).await {
+            Ok(Ok(request)) => request,
+            Ok(Err(e)) => {
+                debug!("http client error: {}", e);
+                let _ = Response::error(400, &e.to_string())
+                    .write(&mut writer)
+                    .await;
+                return;
+            }
+            Err(_) => return,
+        };
+        debug!("http request {} {}", request.method, request.path);
+
+        // check token of the client
+        if !request.is_authorized(&self.token) {
+            let _ = Response::error(401, "invalid token")
+                .write(&mut writer)
+                .await;
+            return;
+        }
+        if let Err(e) = self.handle_request(request, &mut writer).await {
+            debug!("http client error: {}", e);
+            let _ = Response::error(500, &e.to_string())
+                .write(&mut writer)
+                .await;
+        }
+    }
+}
@@ -1,10 +1,13 @@
 #[macro_use]
 extern crate log;
 
+pub mod api;
 mod client;
 mod config;
 mod daemon;
-mod message;
+mod http_socket;
+pub mod message;
+mod tcp_socket;
 mod unix_socket;
 
 pub async fn run() {
@@ -16,6 +19,9 @@ pub async fn run() {
         Some(config::Command::Set(..)) => client::set::run(config).await,
         Some(config::Command::Chat(..)) => client::chat::run(config).await,
         Some(config::Command::Files) => client::file::run(config).await,
+        Some(config::Command::Ping(..)) => client::ping::run(config).await,
+        Some(config::Command::Trace(..)) => client::trace::run(config).await,
+        Some(config::Command::Watch(..)) => client::watch::run(config).await,
         None => (),
     }
 }
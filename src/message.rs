@@ -1,14 +1,18 @@
 use minicbor::{Decode, Encode};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
 /// Service
 pub enum Service {
     Service = 1,
+    /// daemon and service events for watching clients, not announced to
+    /// other peers
+    Watch = 2,
     Chat = 1025,
     File,
 }
 
-#[derive(Clone, Debug, Encode, Decode)]
+#[derive(Clone, Debug, Default, Encode, Decode, Serialize, Deserialize)]
 pub struct PeerInfo {
     #[n(0)]
     pub peer_id: String,
@@ -18,9 +22,70 @@ pub struct PeerInfo {
     pub services_tag: u32,
     #[n(3)]
     pub last_update: u64,
+    #[n(4)]
+    pub agent_version: String,
+    #[n(5)]
+    pub protocol_version: String,
+    #[n(6)]
+    pub listen_addrs: Vec<String>,
+    #[n(7)]
+    pub observed_addr: String,
+    #[n(8)]
+    pub connected: bool,
+    /// round-trip time in microseconds
+    #[n(9)]
+    pub rtt: Option<u64>,
+    /// version of the peer's announcements
+    #[n(10)]
+    pub version: u8,
+    /// request protocol versions supported by the peer
+    #[n(11)]
+    pub request_versions: Vec<String>,
+    /// peer's protocol versions differ from our versions
+    #[n(12)]
+    pub version_mismatch: bool,
 }
 
-#[derive(Clone, Debug, Encode, Decode)]
+/// Message waiting in the daemon's outbox for an unreachable peer
+#[derive(Clone, Debug, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct OutboxInfo {
+    #[n(0)]
+    pub id: u32,
+    #[n(1)]
+    pub to_peer: String,
+    #[n(2)]
+    pub to_client: u16,
+    #[n(3)]
+    pub from_client: u16,
+    #[n(4)]
+    pub service: u16,
+    /// size of the content in bytes
+    #[n(5)]
+    pub size: u64,
+    /// time the message was queued in seconds since the unix epoch
+    #[n(6)]
+    pub created: u64,
+    /// time the message expires in seconds since the unix epoch
+    #[n(7)]
+    pub expires: u64,
+}
+
+/// Local client connected to the daemon
+#[derive(Clone, Debug, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ClientInfo {
+    #[n(0)]
+    pub client_id: u16,
+    /// user id of the client process
+    #[n(1)]
+    pub uid: u32,
+    /// process id of the client process if known
+    #[n(2)]
+    pub pid: Option<i32>,
+    #[n(3)]
+    pub services: Vec<u16>,
+}
+
+#[derive(Clone, Debug, Encode, Decode, Serialize, Deserialize)]
 pub enum GetSet {
     /// Ok message
     #[n(0)]
@@ -45,9 +110,105 @@ pub enum GetSet {
     /// Services tag
     #[n(5)]
     ServicesTag(#[n(0)] u32),
+
+    /// Peer ID of the node
+    #[n(6)]
+    PeerId(#[n(0)] String),
+
+    /// Listen on address
+    #[n(7)]
+    Listen(#[n(0)] String),
+
+    /// Stop listening on address
+    #[n(8)]
+    Unlisten(#[n(0)] String),
+
+    /// Current listen addresses
+    #[n(9)]
+    Addresses(#[n(0)] Vec<String>),
+
+    /// Ping peer: peer ID, round-trip time in microseconds
+    #[n(10)]
+    Ping(#[n(0)] String, #[n(1)] u64),
+
+    /// Block peer: peer ID
+    #[n(11)]
+    Block(#[n(0)] String),
+
+    /// Unblock peer: peer ID
+    #[n(12)]
+    Unblock(#[n(0)] String),
+
+    /// Allow peer: peer ID
+    #[n(13)]
+    Allow(#[n(0)] String),
+
+    /// Disallow peer: peer ID
+    #[n(14)]
+    Disallow(#[n(0)] String),
+
+    /// Blocked peers
+    #[n(15)]
+    Blocked(#[n(0)] Vec<String>),
+
+    /// Allowed peers
+    #[n(16)]
+    Allowed(#[n(0)] Vec<String>),
+
+    /// Trace peer: peer ID, path of peer IDs from this node to the peer
+    #[n(17)]
+    Trace(#[n(0)] String, #[n(1)] Vec<String>),
+
+    /// Messages in the outbox
+    #[n(18)]
+    Outbox(#[n(0)] Vec<OutboxInfo>),
+
+    /// Purge messages in the outbox: peer ID, all peers if empty
+    #[n(19)]
+    PurgeOutbox(#[n(0)] String),
+
+    /// Local clients connected to the daemon
+    #[n(20)]
+    Clients(#[n(0)] Vec<ClientInfo>),
+}
+
+/// Reason of a failed message delivery
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum DeliveryError {
+    /// dialing the peer failed
+    #[n(0)]
+    DialFailure,
+
+    /// peer did not answer in time
+    #[n(1)]
+    Timeout,
+
+    /// peer is unknown or could not be found
+    #[n(2)]
+    UnknownPeer,
+
+    /// peer returned an error
+    #[n(3)]
+    RemoteError(#[n(0)] String),
+
+    /// other error
+    #[n(4)]
+    Other(#[n(0)] String),
+}
+
+impl std::fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DeliveryError::DialFailure => write!(f, "dial failure"),
+            DeliveryError::Timeout => write!(f, "timeout"),
+            DeliveryError::UnknownPeer => write!(f, "unknown peer"),
+            DeliveryError::RemoteError(e) => write!(f, "remote error: {}", e),
+            DeliveryError::Other(e) => write!(f, "{}", e),
+        }
+    }
 }
 
-#[derive(Clone, Debug, Encode, Decode)]
+#[derive(Clone, Debug, Encode, Decode, Serialize, Deserialize)]
 pub enum Event {
     /// client update: add/remove, client id, services
     #[n(0)]
@@ -60,9 +221,35 @@ pub enum Event {
     /// service update: service, map of supporting peers and their clients
     #[n(2)]
     ServiceUpdate(#[n(0)] u16, #[n(1)] HashMap<String, HashSet<u16>>),
+
+    /// message delivered: message id, destination peer
+    #[n(3)]
+    MessageDelivered(#[n(0)] u32, #[n(1)] String),
+
+    /// message delivery failed: message id, destination peer, reason
+    #[n(4)]
+    MessageFailed(#[n(0)] u32, #[n(1)] String, #[n(2)] DeliveryError),
+
+    /// message published on a subscribed topic: topic, source peer, content
+    #[n(5)]
+    TopicMessage(
+        #[n(0)] String,
+        #[n(1)] String,
+        #[n(2)]
+        #[cbor(with = "minicbor::bytes")]
+        Vec<u8>,
+    ),
+
+    /// connection to peer opened or closed: peer ID, connected
+    #[n(6)]
+    PeerConnection(#[n(0)] String, #[n(1)] bool),
+
+    /// peer removed after missing announcements: peer ID
+    #[n(7)]
+    PeerRemoved(#[n(0)] String),
 }
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
 pub enum Message {
     /// Ok message
     #[n(0)]
@@ -127,6 +314,10 @@ pub enum Message {
         #[n(5)]
         #[cbor(with = "minicbor::bytes")]
         content: Vec<u8>,
+        /// id of the message chosen by the sending client, delivery status
+        /// is reported back to the client with this id
+        #[n(6)]
+        message_id: u32,
     },
 
     /// Event
@@ -139,12 +330,39 @@ pub enum Message {
         #[n(2)]
         event: Event,
     },
+
+    /// Subscribe this client to a topic
+    #[n(8)]
+    Subscribe {
+        #[n(0)]
+        topic: String,
+    },
+
+    /// Unsubscribe this client from a topic
+    #[n(9)]
+    Unsubscribe {
+        #[n(0)]
+        topic: String,
+    },
+
+    /// Publish content on a topic
+    #[n(10)]
+    Publish {
+        #[n(0)]
+        topic: String,
+        #[n(1)]
+        #[cbor(with = "minicbor::bytes")]
+        content: Vec<u8>,
+    },
 }
 
 impl Message {
     /// Client ID for all clients on a peer
     pub const ALL_CLIENTS: u16 = u16::MAX;
 
+    /// Peer ID for broadcast messages to all peers
+    pub const ALL_PEERS: &'static str = "*";
+
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
         match minicbor::decode(bytes) {
             Ok(msg) => Some(msg),
@@ -165,4 +383,24 @@ impl Message {
             }
         }
     }
+
+    pub fn from_json(json: &str) -> Option<Self> {
+        match serde_json::from_str(json) {
+            Ok(msg) => Some(msg),
+            Err(e) => {
+                error!("daemon message json deserialization error: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Option<String> {
+        match serde_json::to_string(self) {
+            Ok(json) => Some(json),
+            Err(e) => {
+                error!("daemon message json serialization error: {}", e);
+                None
+            }
+        }
+    }
 }
@@ -0,0 +1,233 @@
+use crate::api;
+use crate::config::Config;
+use crate::message::Message;
+use futures::future::FutureExt;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::fs;
+use tokio::io;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+
+/// file containing the token of the tcp api
+const TOKEN_FILE: &str = "api.token";
+
+/// maximum size of the first line containing the token
+const MAX_AUTH_SIZE: u64 = 1024;
+
+/// time to wait for the token of a client
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// first line sent by tcp clients
+#[derive(Deserialize)]
+struct Auth {
+    token: String,
+}
+
+/// load token of the tcp and http api from the config directory, create a
+/// new token if there is none
+pub async fn load_token(config: &Config) -> io::Result<String> {
+    let mut file = config.dir.clone().unwrap();
+    file.push(TOKEN_FILE);
+    if let Ok(token) = fs::read_to_string(&file).await {
+        let token = token.trim();
+        if !token.is_empty() {
+            return Ok(token.to_string());
+        }
+    }
+
+    // create new token, only readable by the owner
+    let token: String = rand::random::<[u8; 32]>()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&file)
+        .await?;
+    f.write_all(token.as_bytes()).await?;
+    Ok(token)
+}
+
+/// compare `token` sent by a client with the `expected` token in constant
+/// time
+pub fn check_token(expected: &str, token: &str) -> bool {
+    if expected.len() != token.len() {
+        return false;
+    }
+    expected
+        .bytes()
+        .zip(token.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// TCP socket server, speaks newline-delimited JSON messages and forwards
+/// them to the daemon's unix socket
+pub struct TcpServer {
+    listener: TcpListener,
+    dir: PathBuf,
+    token: String,
+}
+
+impl TcpServer {
+    /// Listen on loopback `address`
+    pub async fn listen(config: &Config, address: SocketAddr) -> io::Result<Self> {
+        if !address.ip().is_loopback() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("address {} is not a loopback address", address),
+            ));
+        }
+        let token = load_token(config).await?;
+        let listener = TcpListener::bind(address).await?;
+        Ok(TcpServer {
+            listener,
+            dir: config.dir.clone().unwrap(),
+            token,
+        })
+    }
+
+    /// Accept clients and handle them until an error occurs
+    pub async fn run(self) {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, address)) => {
+                    debug!("tcp client {} connected", address);
+                    let client = TcpClient {
+                        stream,
+                        dir: self.dir.clone(),
+                        token: self.token.clone(),
+                    };
+                    tokio::spawn(client.run());
+                }
+                Err(e) => {
+                    error!("tcp socket server error: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// TCP socket client
+struct TcpClient {
+    stream: TcpStream,
+    dir: PathBuf,
+    token: String,
+}
+
+impl TcpClient {
+    /// Send message as JSON line
+    async fn send_message<W>(writer: &mut W, message: &Message) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        if let Some(mut json) = message.to_json() {
+            json.push('\n');
+            writer.write_all(json.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// Check `token` in the first line sent by the client
+    async fn accept<R>(reader: &mut R, token: &str) -> io::Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let mut line = String::new();
+        reader.take(MAX_AUTH_SIZE).read_line(&mut line).await?;
+        match serde_json::from_str::<Auth>(&line) {
+            Ok(auth) if check_token(token, &auth.token) => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "invalid token",
+            )),
+        }
+    }
+
+    /// Handle client: check its token and forward messages between the
+    /// client and the daemon
+    async fn run(mut self) {
+        let (reader, mut writer) = self.stream.split();
+        let mut reader = BufReader::new(reader);
+
+        // check token of the client
+        let accept = Self::accept(&mut reader, &self.token);
+        let result = match timeout(AUTH_TIMEOUT, accept).await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "no token")),
+        };
+        if let Err(e) = result {
+            error!("tcp client error: {}", e);
+            let message = Message::Error {
+                message: e.to_string(),
+            };
+            let _ = Self::send_message(&mut writer, &message).await;
+            return;
+        }
+
+        // connect to the daemon, messages from the daemon are read in a
+        // separate task, so receiving them in select is cancel safe
+        let (mut receiver, mut sender) = match api::Client::connect(&self.dir).await {
+            Ok(client) => client.into_split(),
+            Err(e) => {
+                error!("tcp client error: {}", e);
+                return;
+            }
+        };
+
+        let mut lines = reader.lines();
+        loop {
+            tokio::select! {
+                // handle messages from the daemon
+                msg = receiver.receive().fuse() => {
+                    let result = match msg {
+                        Ok(msg) => Self::send_message(&mut writer, &msg).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        error!("tcp client error: {}", e);
+                        break;
+                    }
+                },
+
+                // handle lines from the client
+                line = lines.next_line().fuse() => {
+                    let line = match line {
+                        Ok(Some(line)) => line,
+                        Ok(None) => break,
+                        Err(e) => {
+                            error!("tcp client error: {}", e);
+                            break;
+                        }
+                    };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let result = match Message::from_json(&line) {
+                        Some(msg) => sender.send(msg).await,
+                        None => {
+                            let message = Message::Error {
+                                message: String::from("invalid message"),
+                            };
+                            Self::send_message(&mut writer, &message).await
+                        }
+                    };
+                    if let Err(e) = result {
+                        error!("tcp client error: {}", e);
+                        break;
+                    }
+                },
+            }
+        }
+        debug!("tcp client stopped");
+    }
+}
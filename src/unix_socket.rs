@@ -1,29 +1,150 @@
 use crate::config::Config;
 use crate::message::Message;
+use futures::channel::mpsc;
+use futures::sink::SinkExt;
+use futures::StreamExt;
+use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::fs::Permissions;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::str::FromStr;
 use tokio::fs;
 use tokio::io;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::unix::OwnedWriteHalf;
 use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
 
 const SOCKET_FILE: &str = "hi.sock";
 
+/// private directory in which the socket file is created before it is
+/// moved to its final path
+const SOCKET_TMP_DIR: &str = "hi.sock.tmp";
+
+/// magic bytes at the start of the handshake
+const MAGIC: [u8; 4] = *b"hipc";
+
+/// version of the unix socket protocol
+const VERSION: u16 = 1;
+
+/// maximum size of a message
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// time to wait for the handshake of the daemon or a client
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// number of received messages buffered by the receiving half of a client
+const RECEIVE_BUFFER: usize = 64;
+
+/// Credentials of a process connected to the unix socket
+#[derive(Debug, Clone, Copy)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: Option<i32>,
+}
+
+/// Policy for clients connecting to the unix socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientPolicy {
+    /// only allow clients of the owner of the daemon
+    Owner,
+    /// allow clients in the group of the owner of the daemon, only the
+    /// primary group of the client is checked, supplementary groups are
+    /// ignored
+    Group,
+    /// allow clients of the owner of the daemon and the listed users
+    Uids(HashSet<u32>),
+}
+
+impl ClientPolicy {
+    /// get permissions of the socket file for this policy
+    fn mode(&self) -> u32 {
+        match self {
+            ClientPolicy::Owner => 0o600,
+            ClientPolicy::Group => 0o660,
+            ClientPolicy::Uids(..) => 0o666,
+        }
+    }
+
+    /// check if policy allows client with credentials `client` to connect
+    /// to the socket of daemon owner `owner`
+    pub fn allows(&self, owner: &Credentials, client: &Credentials) -> bool {
+        if client.uid == owner.uid {
+            return true;
+        }
+        match self {
+            ClientPolicy::Owner => false,
+            ClientPolicy::Group => client.gid == owner.gid,
+            ClientPolicy::Uids(uids) => uids.contains(&client.uid),
+        }
+    }
+}
+
+impl FromStr for ClientPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "owner" => Ok(ClientPolicy::Owner),
+            "group" => Ok(ClientPolicy::Group),
+            _ => {
+                let mut uids = HashSet::new();
+                for uid in s.split(',') {
+                    match uid.trim().parse() {
+                        Ok(uid) => uids.insert(uid),
+                        Err(_) => return Err(format!("invalid client policy: {}", s)),
+                    };
+                }
+                Ok(ClientPolicy::Uids(uids))
+            }
+        }
+    }
+}
+
 /// Unix socket server
 pub struct UnixServer {
     listener: UnixListener,
+    owner: Credentials,
 }
 
 impl UnixServer {
-    /// Listen on unix socket
-    pub async fn listen(config: &Config) -> io::Result<Self> {
-        let mut socket = config.dir.clone().unwrap();
-        socket.push(SOCKET_FILE);
-        if socket.exists() {
-            // remove old socket file
-            fs::remove_file(&socket).await?;
+    /// Listen on unix socket, permissions of the socket file are set
+    /// according to client `policy`
+    pub async fn listen(config: &Config, policy: &ClientPolicy) -> io::Result<Self> {
+        let dir = config.dir.clone().unwrap();
+        let socket = dir.join(SOCKET_FILE);
+
+        // create socket in a directory only accessible by the owner and set
+        // its permissions before it is visible to other users
+        let tmp_dir = dir.join(SOCKET_TMP_DIR);
+        if tmp_dir.exists() {
+            fs::remove_dir_all(&tmp_dir).await?;
         }
-        let listener = UnixListener::bind(&socket)?;
-        Ok(UnixServer { listener })
+        fs::DirBuilder::new().mode(0o700).create(&tmp_dir).await?;
+        let tmp_socket = tmp_dir.join(SOCKET_FILE);
+        let listener = UnixListener::bind(&tmp_socket)?;
+        fs::set_permissions(&tmp_socket, Permissions::from_mode(policy.mode())).await?;
+
+        // replace old socket file
+        fs::rename(&tmp_socket, &socket).await?;
+        fs::remove_dir(&tmp_dir).await?;
+
+        // owner of the socket file is the user running the daemon
+        let metadata = fs::metadata(&socket).await?;
+        let owner = Credentials {
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            pid: Some(std::process::id() as i32),
+        };
+        Ok(UnixServer { listener, owner })
+    }
+
+    /// Get credentials of the user running the daemon
+    pub fn owner(&self) -> Credentials {
+        self.owner
     }
 
     /// Wait for next client connecting to the unix socket
@@ -42,52 +163,260 @@ pub struct UnixClient {
 }
 
 impl UnixClient {
-    /// Connect to unix socket server and return UnixClient if successful
-    pub async fn connect(config: &Config) -> io::Result<Self> {
-        let mut socket = config.dir.clone().unwrap();
-        socket.push(SOCKET_FILE);
+    /// Connect to unix socket server in directory `dir` and return
+    /// UnixClient if successful
+    pub async fn connect(dir: &Path) -> io::Result<Self> {
+        let socket = dir.join(SOCKET_FILE);
         let stream = UnixStream::connect(socket).await?;
-        Ok(UnixClient { stream })
+        let mut client = UnixClient { stream };
+
+        // send handshake and check handshake of the daemon
+        client.send_handshake().await?;
+        match timeout(HANDSHAKE_TIMEOUT, client.receive_handshake()).await {
+            Ok(result) => result?,
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no handshake from daemon, incompatible daemon version?",
+                ))
+            }
+        }
+        Ok(client)
+    }
+
+    /// Get credentials of the process on the other end of the socket
+    pub fn peer_credentials(&self) -> io::Result<Credentials> {
+        let cred = self.stream.peer_cred()?;
+        Ok(Credentials {
+            uid: cred.uid(),
+            gid: cred.gid(),
+            pid: cred.pid(),
+        })
     }
 
-    /// Send bytes with prefixed length
-    async fn send(&mut self, bytes: Vec<u8>) -> io::Result<()> {
-        let len = match u16::try_from(bytes.len()) {
-            Ok(len) => len.to_be_bytes(),
-            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+    /// Accept handshake of a client connecting to the daemon
+    pub async fn accept(&mut self) -> io::Result<()> {
+        let result = match timeout(HANDSHAKE_TIMEOUT, self.receive_handshake()).await {
+            Ok(result) => result,
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no handshake from client",
+                ))
+            }
         };
-        self.stream.write_all(&len).await?;
-        self.stream.write_all(&bytes).await?;
+        self.send_handshake().await?;
+        result
+    }
+
+    /// Send handshake with magic bytes and protocol version
+    async fn send_handshake(&mut self) -> io::Result<()> {
+        self.stream.write_all(&MAGIC).await?;
+        self.stream.write_all(&VERSION.to_be_bytes()).await?;
         Ok(())
     }
 
-    /// Receive bytes with prefixed length
-    async fn receive(&mut self) -> io::Result<Vec<u8>> {
-        let mut len = [0; 2];
-        self.stream.read_exact(&mut len).await?;
-        let len = u16::from_be_bytes(len).into();
-        let mut bytes = vec![0; len];
-        self.stream.read_exact(&mut bytes).await?;
-        Ok(bytes)
+    /// Receive handshake and check magic bytes and protocol version
+    async fn receive_handshake(&mut self) -> io::Result<()> {
+        let mut magic = [0; 4];
+        self.stream.read_exact(&mut magic).await?;
+        if magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid handshake, incompatible version?",
+            ));
+        }
+        let mut version = [0; 2];
+        self.stream.read_exact(&mut version).await?;
+        let version = u16::from_be_bytes(version);
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("incompatible version {}, expected {}", version, VERSION),
+            ));
+        }
+        Ok(())
     }
 
     /// Send daemon message
     pub async fn send_message(&mut self, message: Message) -> io::Result<()> {
-        if let Some(bytes) = message.to_bytes() {
-            self.send(bytes).await?;
-        }
-        Ok(())
+        send_message(&mut self.stream, message).await
     }
 
     /// Receive daemon message
     pub async fn receive_message(&mut self) -> io::Result<Message> {
-        let bytes = self.receive().await?;
-        match Message::from_bytes(&bytes) {
-            Some(msg) => Ok(msg),
+        receive_message(&mut self.stream).await
+    }
+
+    /// Split client into a receiver that reads daemon messages in a
+    /// separate task and a sender of daemon messages
+    pub fn into_split(self) -> (UnixReceiver, UnixSender) {
+        let (mut reader, writer) = self.stream.into_split();
+        let (mut sender, receiver) = mpsc::channel(RECEIVE_BUFFER);
+        let task = tokio::spawn(async move {
+            // read whole messages here, so receiving from the channel can
+            // be cancelled safely, e.g., in select
+            loop {
+                let result = receive_message(&mut reader).await;
+                let is_err = result.is_err();
+                if sender.send(result).await.is_err() || is_err {
+                    return;
+                }
+            }
+        });
+        let receiver = UnixReceiver { receiver, task };
+        let sender = UnixSender { writer };
+        (receiver, sender)
+    }
+}
+
+/// Receiving half of a unix socket client
+pub struct UnixReceiver {
+    receiver: mpsc::Receiver<io::Result<Message>>,
+    task: JoinHandle<()>,
+}
+
+impl UnixReceiver {
+    /// Receive daemon message, cancel safe
+    pub async fn receive_message(&mut self) -> io::Result<Message> {
+        match self.receiver.next().await {
+            Some(result) => result,
             None => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "error receiving message",
+                io::ErrorKind::UnexpectedEof,
+                "connection closed",
             )),
         }
     }
 }
+
+impl Drop for UnixReceiver {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Sending half of a unix socket client
+pub struct UnixSender {
+    writer: OwnedWriteHalf,
+}
+
+impl UnixSender {
+    /// Send daemon message
+    pub async fn send_message(&mut self, message: Message) -> io::Result<()> {
+        send_message(&mut self.writer, message).await
+    }
+}
+
+/// Send bytes with prefixed length
+async fn send<W: AsyncWrite + Unpin>(writer: &mut W, bytes: Vec<u8>) -> io::Result<()> {
+    if bytes.len() > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("message size {} too big", bytes.len()),
+        ));
+    }
+    let len = match u32::try_from(bytes.len()) {
+        Ok(len) => len.to_be_bytes(),
+        Err(e) => return Err(io::Error::other(e)),
+    };
+    writer.write_all(&len).await?;
+    writer.write_all(&bytes).await?;
+    Ok(())
+}
+
+/// Receive bytes with prefixed length
+async fn receive<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message size {} too big", len),
+        ));
+    }
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes).await?;
+    Ok(bytes)
+}
+
+/// Send daemon message
+async fn send_message<W: AsyncWrite + Unpin>(writer: &mut W, message: Message) -> io::Result<()> {
+    if let Some(bytes) = message.to_bytes() {
+        send(writer, bytes).await?;
+    }
+    Ok(())
+}
+
+/// Receive daemon message
+async fn receive_message<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Message> {
+    let bytes = receive(reader).await?;
+    match Message::from_bytes(&bytes) {
+        Some(msg) => Ok(msg),
+        None => Err(io::Error::other("error receiving message")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// create credentials of user `uid` with primary group `gid`
+    fn credentials(uid: u32, gid: u32) -> Credentials {
+        Credentials {
+            uid,
+            gid,
+            pid: None,
+        }
+    }
+
+    #[test]
+    fn parse_policy() {
+        assert_eq!("owner".parse(), Ok(ClientPolicy::Owner));
+        assert_eq!("group".parse(), Ok(ClientPolicy::Group));
+        let uids = HashSet::from([1000, 1001]);
+        assert_eq!("1000,1001".parse(), Ok(ClientPolicy::Uids(uids.clone())));
+        assert_eq!("1000, 1001".parse(), Ok(ClientPolicy::Uids(uids)));
+        let uids = HashSet::from([1000]);
+        assert_eq!("1000".parse(), Ok(ClientPolicy::Uids(uids)));
+    }
+
+    #[test]
+    fn reject_invalid_policy() {
+        assert!("".parse::<ClientPolicy>().is_err());
+        assert!("Owner".parse::<ClientPolicy>().is_err());
+        assert!("1000,".parse::<ClientPolicy>().is_err());
+        assert!("1000,user".parse::<ClientPolicy>().is_err());
+        assert!("-1".parse::<ClientPolicy>().is_err());
+        assert!("4294967296".parse::<ClientPolicy>().is_err());
+    }
+
+    #[test]
+    fn owner_policy() {
+        let owner = credentials(1000, 1000);
+        let policy = ClientPolicy::Owner;
+        assert!(policy.allows(&owner, &credentials(1000, 1000)));
+        assert!(policy.allows(&owner, &credentials(1000, 2000)));
+        assert!(!policy.allows(&owner, &credentials(1001, 1000)));
+        assert!(!policy.allows(&owner, &credentials(0, 0)));
+    }
+
+    #[test]
+    fn group_policy() {
+        let owner = credentials(1000, 1000);
+        let policy = ClientPolicy::Group;
+        assert!(policy.allows(&owner, &credentials(1000, 2000)));
+        assert!(policy.allows(&owner, &credentials(1001, 1000)));
+        assert!(!policy.allows(&owner, &credentials(1001, 1001)));
+    }
+
+    #[test]
+    fn uids_policy() {
+        let owner = credentials(1000, 1000);
+        let policy = ClientPolicy::Uids(HashSet::from([1001, 1002]));
+        assert!(policy.allows(&owner, &credentials(1000, 1000)));
+        assert!(policy.allows(&owner, &credentials(1001, 1001)));
+        assert!(policy.allows(&owner, &credentials(1002, 2000)));
+        assert!(!policy.allows(&owner, &credentials(1003, 1000)));
+    }
+}